@@ -37,5 +37,5 @@ fn main() {
     });
 
     json["name"] = json!("john");
-    println!("{} \n{}", json["name"], json.to_string());
+    println!("{} \n{}", json["name"], json);
 }
@@ -1,11 +1,27 @@
+use std::collections::HashMap;
 use std::io::{stdout, Write};
+use std::net::SocketAddr;
+use std::path::Path;
 
 use clap::Parser;
-use dialoguer::{theme::ColorfulTheme, Input, MultiSelect};
+use indexmap::IndexMap;
+use tokio::fs;
+use url::Url;
 use xdiff::{
-    cli::{parse_key_val, KeyVal},
-    utils::{highlight, process_error_output},
-    DiffConfig, DiffProfile, ExtraArgs, LoadYaml, RequestProfile,
+    cli::{
+        arg_or_prompt, arg_or_stdin, merge_config, parse_key_val, profile_cases,
+        select_har_entries, select_skip_body, select_skip_headers, CassetteArgs, KeyVal,
+        RequestArgs,
+    },
+    config::case_slug,
+    cookie,
+    import::{self, OpenApi},
+    proxy::Mirror,
+    utils::{
+        assertion_report, highlight, process_error_output, replay_report, response_diff_report,
+        skipped_report, snapshot_report,
+    },
+    AssertionResult, DiffConfig, DiffProfile, ExtraArgs, LoadYaml, ResponseProfile,
 };
 
 use anyhow::{anyhow, Result};
@@ -23,10 +39,47 @@ pub struct Args {
 pub enum Action {
     Run(RunArgs),
     Parse(ParseArgs),
+    Snapshot(SnapshotArgs),
+    ParseCurl(ParseCurlArgs),
+    ExportCurl(ExportCurlArgs),
+    ImportHar(ImportHarArgs),
+    ImportOpenapi(ImportOpenapiArgs),
+    Learn(LearnArgs),
+    Proxy(ProxyArgs),
+    Replay(ReplayArgs),
 }
 
+/// Create a diff profile from two urls.
+///
+/// Missing urls and the name are prompted for, then both requests are sent to choose the
+/// headers and body values to skip. Given all of them, nothing is sent and only the skips given
+/// as flags are used.
 #[derive(Parser, Debug, Clone)]
-pub struct ParseArgs {}
+pub struct ParseArgs {
+    /// url of request1
+    #[clap(long, value_parser)]
+    pub url1: Option<String>,
+    /// url of request2
+    #[clap(long, value_parser)]
+    pub url2: Option<String>,
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub name: Option<String>,
+    #[clap(flatten)]
+    pub request: RequestArgs,
+    /// header to skip in the diff
+    #[clap(short, long, value_parser, number_of_values = 1)]
+    pub skip_header: Vec<String>,
+    /// json path of a body value to skip in the diff, e.g. `items[*].id`
+    #[clap(short = 'b', long, value_parser, number_of_values = 1)]
+    pub skip_body: Vec<String>,
+    /// add the profile to this config file instead of printing it
+    #[clap(short, long, value_parser)]
+    pub append: Option<String>,
+    /// replace a profile of the same name in the config file
+    #[clap(short, long, requires = "append")]
+    pub force: bool,
+}
 
 #[derive(Parser, Debug, Clone)]
 pub struct RunArgs {
@@ -43,6 +96,158 @@ pub struct RunArgs {
     /// config file
     #[clap(short, long, value_parser)]
     pub config: Option<String>,
+    #[clap(flatten)]
+    pub cassette: CassetteArgs,
+    /// send the requests this many times to measure their timing
+    #[clap(short, long, value_parser = clap::value_parser!(u16).range(1..), default_value = "1")]
+    pub repeat: u16,
+    /// print the status, header and body differences as json
+    #[clap(long)]
+    pub json: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct SnapshotArgs {
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub profile: String,
+
+    /// Override args. Same as for `run`.
+    #[clap(short, long, value_parser = parse_key_val, number_of_values=1)]
+    pub extra_params: Vec<KeyVal>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    #[clap(flatten)]
+    pub cassette: CassetteArgs,
+    /// directory the snapshots are stored in
+    #[clap(short, long, value_parser, default_value = "snapshots")]
+    pub dir: String,
+    /// accept the live response as the new snapshot
+    #[clap(short, long)]
+    pub update: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ParseCurlArgs {
+    /// which side of the diff profile the request is for
+    #[clap(short, long, value_parser = ["request1", "request2"], default_value = "request1")]
+    pub side: String,
+    /// curl command, read from stdin when omitted
+    #[clap(value_parser)]
+    pub command: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ExportCurlArgs {
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub profile: String,
+
+    /// Override args. Same as for `run`.
+    #[clap(short, long, value_parser = parse_key_val, number_of_values=1)]
+    pub extra_params: Vec<KeyVal>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    /// only export this request of the diff profile, e.g. `request1`
+    #[clap(short, long, value_parser)]
+    pub side: Option<String>,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ImportHarArgs {
+    /// HAR file
+    #[clap(value_parser)]
+    pub file: String,
+    /// import the entries whose url matches this regex instead of choosing them
+    #[clap(short, long, value_parser)]
+    pub filter: Option<String>,
+    /// base url of the second environment, e.g. https://staging.example.com, its path is kept
+    /// as a prefix
+    #[clap(long, value_parser)]
+    pub host2: Url,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct ImportOpenapiArgs {
+    /// OpenAPI 3 document, yaml or json
+    #[clap(value_parser)]
+    pub file: String,
+    /// server url for request1, defaults to the first server of the document
+    #[clap(long, value_parser)]
+    pub server1: Option<String>,
+    /// server url for request2, defaults to the second server of the document
+    #[clap(long, value_parser)]
+    pub server2: Option<String>,
+    /// url the document is served from, relative server urls such as /api are resolved against it
+    #[clap(long, value_parser, default_value = "http://localhost")]
+    pub base: Url,
+}
+
+/// Find the headers and body values that change between identical requests.
+#[derive(Parser, Debug, Clone)]
+pub struct LearnArgs {
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub profile: String,
+
+    /// Override args. Same as for `run`.
+    #[clap(short, long, value_parser = parse_key_val, number_of_values=1)]
+    pub extra_params: Vec<KeyVal>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    /// how many times each side is requested
+    #[clap(short, long, value_parser = clap::value_parser!(u16).range(2..), default_value = "3")]
+    pub samples: u16,
+    /// save the result into the config file instead of printing it
+    #[clap(short, long)]
+    pub write: bool,
+}
+
+/// Serve the primary's responses and diff the candidate's against them in the background.
+#[derive(Parser, Debug, Clone)]
+pub struct ProxyArgs {
+    /// address to accept requests on
+    #[clap(short, long, value_parser, default_value = "127.0.0.1:8080")]
+    pub listen: SocketAddr,
+    /// base url of the upstream whose responses are returned
+    #[clap(long, value_parser)]
+    pub primary: Url,
+    /// base url of the upstream compared with the primary
+    #[clap(long, value_parser)]
+    pub candidate: Url,
+    /// profile whose response settings, like skip_headers, are used for the diff
+    #[clap(short, long, value_parser)]
+    pub profile: Option<String>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+}
+
+/// Send the requests of a JSONL access log to two environments and summarize the differences.
+/// Each line is an object with `method`, `path`, and optionally `query`, `body` and `headers`.
+#[derive(Parser, Debug, Clone)]
+pub struct ReplayArgs {
+    /// JSONL access log
+    #[clap(value_parser)]
+    pub file: String,
+    /// base url of the first environment
+    #[clap(long, value_parser)]
+    pub base1: Url,
+    /// base url of the second environment
+    #[clap(long, value_parser)]
+    pub base2: Url,
+    /// profile whose response settings, like skip_headers, are used for the diff
+    #[clap(short, long, value_parser)]
+    pub profile: Option<String>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    /// number of log entries replayed at the same time
+    #[clap(short = 'n', long, value_parser, default_value = "4")]
+    pub concurrency: usize,
 }
 
 #[tokio::main]
@@ -51,47 +256,183 @@ async fn main() -> Result<()> {
 
     let result = match args.action {
         Action::Run(args) => run(args).await,
-        Action::Parse(_) => parse().await,
+        Action::Parse(args) => parse(args).await,
+        Action::Snapshot(args) => snapshot(args).await,
+        Action::ParseCurl(args) => parse_curl(args),
+        Action::ExportCurl(args) => export_curl(args).await,
+        Action::ImportHar(args) => import_har(args).await,
+        Action::ImportOpenapi(args) => import_openapi(args).await,
+        Action::Learn(args) => learn(args).await,
+        Action::Proxy(args) => proxy(args).await,
+        Action::Replay(args) => replay(args).await,
     };
+    let result = result.and(cookie::save_all().await);
+
+    let failed = result.is_err();
+    process_error_output(result)?;
+    if failed {
+        std::process::exit(1);
+    }
 
-    process_error_output(result)
+    Ok(())
 }
 
-async fn parse() -> Result<()> {
-    let theme = ColorfulTheme::default();
-    let url1: String = Input::with_theme(&theme)
-        .with_prompt("Please enter url1")
-        .interact_text()?;
+async fn parse(args: ParseArgs) -> Result<()> {
+    let interactive = args.url1.is_none() || args.url2.is_none() || args.name.is_none();
+    let url1 = arg_or_prompt(args.url1, "Please enter url1")?;
+    let url2 = arg_or_prompt(args.url2, "Please enter url2")?;
+    let profile_name = arg_or_prompt(args.name, "Please enter profile name")?;
 
-    let url2: String = Input::with_theme(&theme)
-        .with_prompt("Please enter url2")
-        .interact_text()?;
+    let req1 = args.request.build(&url1)?;
+    let req2 = args.request.build(&url2)?;
 
-    let profile_name: String = Input::with_theme(&theme)
-        .with_prompt("Please enter profile name")
-        .interact_text()?;
+    let mut response = ResponseProfile::new(args.skip_header, args.skip_body);
+    if interactive {
+        let res1 = req1.send(&ExtraArgs::default()).await?;
+        let res2 = req2.send(&ExtraArgs::default()).await?;
 
-    let req1: RequestProfile = url1.parse()?;
-    let req2: RequestProfile = url2.parse()?;
+        let skip_headers = select_skip_headers(&[&res1, &res2], &response)?;
+        let skip_body = select_skip_body(res1.body(), res2.body(), &response)?;
+        response.skip_headers.extend(skip_headers);
+        response.skip_body.extend(skip_body);
+    }
 
-    let res1 = req1.send(&ExtraArgs::default()).await?;
-    let res2 = req2.send(&ExtraArgs::default()).await?;
+    let mut profile = DiffProfile::new(req1, req2, vec![]);
+    profile.response = response;
+    let config: DiffConfig = DiffConfig::new(vec![(profile_name, profile)].into_iter().collect());
 
-    let header_options = [res1.get_headers(), res2.get_headers()].concat();
-    let chosen = MultiSelect::with_theme(&theme)
-        .with_prompt("Choose headers to skip")
-        .items(&header_options)
-        .interact()?;
+    let output = serde_yaml::to_string(&config)?;
 
-    let skip_headers = chosen
-        .iter()
-        .map(|&i| header_options[i].to_string())
-        .collect::<Vec<_>>();
+    let mut stdout = stdout().lock();
+    match args.append {
+        Some(path) => {
+            merge_config::<DiffConfig>(&path, &output, args.force).await?;
+            writeln!(stdout, "Profile added to {}", path)?;
+        }
+        None => writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?,
+    }
 
-    let profile = DiffProfile::new(req1, req2, skip_headers);
-    let config: DiffConfig = DiffConfig::new(vec![(profile_name, profile)].into_iter().collect());
+    Ok(())
+}
 
-    let output = serde_yaml::to_string(&config)?;
+async fn run(args: RunArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "xdiff.yaml".to_string());
+    let config = DiffConfig::load_yaml(&config_file).await?;
+    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+        anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+    let extra_args = ExtraArgs::from(args.extra_params).with_cassette(args.cassette.load().await?);
+
+    let repeat = args.repeat as usize;
+
+    if profile.matrix.is_none() {
+        let report = profile.diff(&extra_args, repeat).await?;
+        if args.json {
+            writeln!(
+                stdout().lock(),
+                "{}",
+                serde_json::to_string_pretty(&report)?
+            )?;
+        } else {
+            writeln!(stdout().lock(), "------\n{}", report.output)?;
+        }
+
+        return match report.failure() {
+            Some(message) => Err(anyhow!(message)),
+            None => Ok(()),
+        };
+    }
+
+    let mut results = vec![];
+    let mut reports = serde_json::Map::new();
+    for (name, case) in profile.cases().await? {
+        let message = match case.diff(&extra_args, repeat).await {
+            Ok(report) => {
+                if args.json {
+                    reports.insert(name.clone(), serde_json::to_value(&report)?);
+                } else {
+                    writeln!(stdout().lock(), "------\n# {}\n{}", name, report.output)?;
+                }
+                report.failure()
+            }
+            Err(e) => {
+                if args.json {
+                    reports.insert(name.clone(), serde_json::json!({ "error": e.to_string() }));
+                }
+                Some(e.to_string())
+            }
+        };
+        results.push(AssertionResult {
+            name,
+            passed: message.is_none(),
+            message,
+        });
+    }
+
+    let failed = results.iter().filter(|r| !r.passed).count();
+    if args.json {
+        writeln!(
+            stdout().lock(),
+            "{}",
+            serde_json::to_string_pretty(&reports)?
+        )?;
+    } else {
+        writeln!(stdout().lock(), "------\n{}", assertion_report(&results)?)?;
+    }
+    if failed > 0 {
+        return Err(anyhow!("{} of {} cases failed", failed, results.len()));
+    }
+
+    Ok(())
+}
+
+async fn snapshot(args: SnapshotArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "xdiff.yaml".to_string());
+    let config = DiffConfig::load_yaml(&config_file).await?;
+    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+        anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+    let extra_args = ExtraArgs::from(args.extra_params).with_cassette(args.cassette.load().await?);
+
+    let mut results = vec![];
+    for (case, profile) in profile_cases(profile).await? {
+        let name = match &case {
+            Some(case) => format!("{}.{}", args.profile, case_slug(case)),
+            None => args.profile.clone(),
+        };
+        let statuses = profile
+            .snapshot(Path::new(&args.dir), &name, &extra_args, args.update)
+            .await?;
+        for (side, status) in statuses {
+            results.push((format!("{}.{}", name, side), status));
+        }
+    }
+
+    let mut stdout = stdout().lock();
+    for (name, status) in &results {
+        writeln!(stdout, "------\n{}", snapshot_report(name, status))?;
+    }
+
+    if results.iter().any(|(_, status)| status.is_changed()) {
+        return Err(anyhow!("snapshot changed, rerun with --update to accept"));
+    }
+
+    Ok(())
+}
+
+fn parse_curl(args: ParseCurlArgs) -> Result<()> {
+    let request = import::parse_curl(&arg_or_stdin(args.command)?)?;
+    let side: HashMap<_, _> = vec![(args.side, request)].into_iter().collect();
+
+    let output = serde_yaml::to_string(&side)?;
 
     let mut stdout = stdout().lock();
     writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
@@ -99,7 +440,7 @@ async fn parse() -> Result<()> {
     Ok(())
 }
 
-async fn run(args: RunArgs) -> Result<()> {
+async fn export_curl(args: ExportCurlArgs) -> Result<()> {
     let config_file = args.config.unwrap_or_else(|| "xdiff.yaml".to_string());
     let config = DiffConfig::load_yaml(&config_file).await?;
     let profile = config.get_profile(&args.profile).ok_or_else(|| {
@@ -111,11 +452,217 @@ async fn run(args: RunArgs) -> Result<()> {
     })?;
     let extra_args = ExtraArgs::from(args.extra_params);
 
-    let output = profile.diff(extra_args).await?;
+    let sides = profile.sides();
+    if let Some(side) = &args.side {
+        if !sides.iter().any(|(name, _)| name == side) {
+            return Err(anyhow!("Profile {} has no request {}", args.profile, side));
+        }
+    }
 
     let mut stdout = stdout().lock();
+    for (case, profile) in profile_cases(profile).await? {
+        for (side, request) in profile.sides() {
+            if args.side.as_ref().is_some_and(|v| v != side) {
+                continue;
+            }
+            let title = match &case {
+                Some(case) => format!("{} ({})", side, case),
+                None => side.to_string(),
+            };
+            let output = format!("# {}\n{}", title, request.to_curl(&extra_args)?);
+            writeln!(stdout, "{}", highlight(&output, "sh")?)?;
+        }
+    }
 
-    writeln!(stdout, "------\n{}", output)?;
+    Ok(())
+}
+
+async fn import_har(args: ImportHarArgs) -> Result<()> {
+    let entries = import::load_har(&fs::read_to_string(&args.file).await?)?;
+    let entries = select_har_entries(entries, args.filter.as_deref())?;
+
+    let mut profiles = IndexMap::new();
+    let mut skipped = vec![];
+    for entry in entries {
+        let imported = entry.to_request().and_then(|request1| {
+            let mut request2 = request1.clone();
+            request2.url = import::swap_origin(&request1.url, &args.host2)?;
+            let name = import::profile_name(&request1.method, &entry.url()?, profiles.keys());
+            Ok((name, DiffProfile::new(request1, request2, vec![])))
+        });
+        match imported {
+            Ok((name, profile)) => {
+                profiles.insert(name, profile);
+            }
+            Err(e) => skipped.push((entry.request.url, e)),
+        }
+    }
+
+    let output = serde_yaml::to_string(&DiffConfig::new(profiles))?;
+
+    let mut stdout = stdout().lock();
+    writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+    if !skipped.is_empty() {
+        writeln!(stdout, "------\n{}", skipped_report(&skipped))?;
+    }
 
     Ok(())
 }
+
+/// Operations which can not be converted are reported and left out.
+async fn import_openapi(args: ImportOpenapiArgs) -> Result<()> {
+    let spec = fs::read_to_string(&args.file)
+        .await?
+        .parse::<OpenApi>()?
+        .with_base(args.base.clone());
+    let mut servers = spec.servers().into_iter();
+    let mut server = |arg: Option<String>, flag: &str| {
+        arg.or_else(|| servers.next())
+            .ok_or_else(|| anyhow!("Not enough servers in {}, use {}", args.file, flag))
+    };
+    let server1 = server(args.server1.clone(), "--server1")?;
+    let server2 = server(args.server2.clone(), "--server2")?;
+
+    let mut profiles = IndexMap::new();
+    let mut skipped = vec![];
+    // both servers yield the same operations in the same order
+    for ((name, request1), (_, request2)) in spec
+        .requests(&server1)?
+        .into_iter()
+        .zip(spec.requests(&server2)?)
+    {
+        match request1.and_then(|request1| Ok((request1, request2?))) {
+            Ok((request1, request2)) => {
+                profiles.insert(name, DiffProfile::new(request1, request2, vec![]));
+            }
+            Err(e) => skipped.push((name, e)),
+        }
+    }
+
+    let output = serde_yaml::to_string(&DiffConfig::new(profiles))?;
+
+    let mut stdout = stdout().lock();
+    writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+    if !skipped.is_empty() {
+        writeln!(stdout, "------\n{}", skipped_report(&skipped))?;
+    }
+
+    Ok(())
+}
+
+async fn learn(args: LearnArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "xdiff.yaml".to_string());
+    let mut config = DiffConfig::load_yaml(&config_file).await?;
+    let mut profile = config.profiles.shift_remove(&args.profile).ok_or_else(|| {
+        anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+    let extra_args = ExtraArgs::from(args.extra_params);
+
+    // every case of a matrix is sampled, adding to what the previous ones found
+    let mut response = profile.response.clone();
+    for (_, mut case) in profile_cases(&profile).await? {
+        case.response = response;
+        response = case.learn(args.samples as usize, &extra_args).await?;
+    }
+    profile.response = response;
+
+    let mut stdout = stdout().lock();
+    if args.write {
+        let config = DiffConfig::new(vec![(args.profile, profile)].into_iter().collect());
+        merge_config::<DiffConfig>(&config_file, &serde_yaml::to_string(&config)?, true).await?;
+        writeln!(stdout, "Profile updated in {}", config_file)?;
+    } else {
+        let response: HashMap<_, _> = vec![("response", &profile.response)].into_iter().collect();
+        let output = serde_yaml::to_string(&response)?;
+        writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+    }
+
+    Ok(())
+}
+
+async fn proxy(args: ProxyArgs) -> Result<()> {
+    let response = load_response(args.config, args.profile.as_deref()).await?;
+
+    writeln!(
+        stdout().lock(),
+        "Mirroring {} to {} and {}",
+        args.listen,
+        args.primary,
+        args.candidate
+    )?;
+    Mirror::new(args.primary, args.candidate, response)?
+        .serve(args.listen)
+        .await
+}
+
+async fn replay(args: ReplayArgs) -> Result<()> {
+    let response = load_response(args.config, args.profile.as_deref()).await?;
+    let entries = import::load_log(&fs::read_to_string(&args.file).await?)?;
+
+    let results = xdiff::replay::replay(
+        entries,
+        &args.base1,
+        &args.base2,
+        &response,
+        args.concurrency,
+    )
+    .await;
+
+    let mut stdout = stdout().lock();
+    for result in &results {
+        let label = format!(
+            "{} {}",
+            result.entry.method.to_uppercase(),
+            result.entry.path
+        );
+        if let Some(error) = &result.error {
+            writeln!(stdout, "{}: {}", label, error)?;
+        } else if !result.diff.is_empty() {
+            let kinds = result
+                .differences()
+                .iter()
+                .map(|d| d.to_string())
+                .collect::<Vec<_>>();
+            writeln!(
+                stdout,
+                "{}: {} differ\n{}",
+                label,
+                kinds.join(", "),
+                response_diff_report(&result.diff)?.trim_end()
+            )?;
+        }
+    }
+    writeln!(stdout, "------\n{}", replay_report(&results)?)?;
+
+    let differ = results.iter().filter(|r| !r.diff.is_empty()).count();
+    let failed = results.iter().filter(|r| r.error.is_some()).count();
+    if differ + failed > 0 {
+        return Err(anyhow!(
+            "{} of {} requests differ, {} failed",
+            differ,
+            results.len(),
+            failed
+        ));
+    }
+
+    Ok(())
+}
+
+/// The response settings of a profile, or the defaults when no profile is given.
+async fn load_response(config: Option<String>, profile: Option<&str>) -> Result<ResponseProfile> {
+    let Some(name) = profile else {
+        return Ok(ResponseProfile::default());
+    };
+
+    let config_file = config.unwrap_or_else(|| "xdiff.yaml".to_string());
+    let config = DiffConfig::load_yaml(&config_file).await?;
+    let profile = config
+        .get_profile(name)
+        .ok_or_else(|| anyhow!("Profile {} not found in config file {}", name, config_file))?;
+
+    Ok(profile.response.clone())
+}
@@ -1,13 +1,28 @@
 use std::io::{stdout, Write};
+use std::path::Path;
+use std::time::Duration;
 
 use anyhow::{anyhow, Result};
 use atty::Stream;
 use clap::Parser;
-use dialoguer::{theme::ColorfulTheme, Input, MultiSelect};
+use indexmap::IndexMap;
+use serde_json::json;
+use tokio::fs;
+use url::Url;
 use xdiff::{
-    cli::{parse_key_val, KeyVal},
-    utils::{highlight, process_error_output},
-    ExtraArgs, LoadYaml, ReqConfig, ReqProfile, RequestProfile,
+    cli::{
+        arg_or_prompt, arg_or_stdin, merge_config, parse_key_val, parse_var, profile_cases,
+        select_har_entries, select_skip_headers, CassetteArgs, KeyVal, RequestArgs,
+    },
+    config::case_slug,
+    cookie,
+    import::{self, OpenApi, PostmanCollection},
+    load::LoadOptions,
+    utils::{
+        assertion_report, highlight, load_report, process_error_output, schema_report,
+        skipped_report, snapshot_report,
+    },
+    AssertionResult, ExtraArgs, LoadYaml, ReqConfig, ReqProfile, ResponseProfile,
 };
 
 #[derive(Debug, Parser, Clone)]
@@ -21,6 +36,13 @@ pub struct Args {
 pub enum Action {
     Run(RunArgs),
     Parse(ParseArgs),
+    Snapshot(SnapshotArgs),
+    ParseCurl(ParseCurlArgs),
+    ExportCurl(ExportCurlArgs),
+    ImportHar(ImportHarArgs),
+    ImportOpenapi(ImportOpenapiArgs),
+    ImportPostman(ImportPostmanArgs),
+    Load(LoadArgs),
 }
 
 #[derive(Debug, Parser, Clone)]
@@ -38,10 +60,141 @@ pub struct RunArgs {
     /// config file
     #[clap(short, long, value_parser)]
     pub config: Option<String>,
+    #[clap(flatten)]
+    pub cassette: CassetteArgs,
 }
 
+/// Create a request profile from a url.
+///
+/// A missing url or name is prompted for, then the request is sent to choose the headers to
+/// skip. Given both, nothing is sent and only the skips given as flags are used.
 #[derive(Debug, Parser, Clone)]
-pub struct ParseArgs {}
+pub struct ParseArgs {
+    /// url of the request
+    #[clap(short, long, value_parser)]
+    pub url: Option<String>,
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub name: Option<String>,
+    #[clap(flatten)]
+    pub request: RequestArgs,
+    /// header to leave out of the output
+    #[clap(short, long, value_parser, number_of_values = 1)]
+    pub skip_header: Vec<String>,
+    /// add the profile to this config file instead of printing it
+    #[clap(short, long, value_parser)]
+    pub append: Option<String>,
+    /// replace a profile of the same name in the config file
+    #[clap(short, long, requires = "append")]
+    pub force: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct SnapshotArgs {
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub profile: String,
+
+    /// Override args. Same as for `run`.
+    #[clap(short, long, value_parser = parse_key_val, number_of_values=1)]
+    pub extra_params: Vec<KeyVal>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    #[clap(flatten)]
+    pub cassette: CassetteArgs,
+    /// directory the snapshots are stored in
+    #[clap(short, long, value_parser, default_value = "snapshots")]
+    pub dir: String,
+    /// accept the live response as the new snapshot
+    #[clap(short, long)]
+    pub update: bool,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ParseCurlArgs {
+    /// profile name
+    #[clap(short, long, value_parser, default_value = "curl")]
+    pub name: String,
+    /// curl command, read from stdin when omitted
+    #[clap(value_parser)]
+    pub command: Option<String>,
+}
+
+/// Print the request of a profile as a curl command. The workflow steps of the profile are sent
+/// to resolve the variables they extract, unless they are replayed with `--cassette`.
+#[derive(Debug, Parser, Clone)]
+pub struct ExportCurlArgs {
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub profile: String,
+
+    /// Override args. Same as for `run`.
+    #[clap(short, long, value_parser = parse_key_val, number_of_values=1)]
+    pub extra_params: Vec<KeyVal>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    #[clap(flatten)]
+    pub cassette: CassetteArgs,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportHarArgs {
+    /// HAR file
+    #[clap(value_parser)]
+    pub file: String,
+    /// import the entries whose url matches this regex instead of choosing them
+    #[clap(short, long, value_parser)]
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportOpenapiArgs {
+    /// OpenAPI 3 document, yaml or json
+    #[clap(value_parser)]
+    pub file: String,
+    /// server url, defaults to the first server of the document
+    #[clap(short, long, value_parser)]
+    pub server: Option<String>,
+    /// url the document is served from, relative server urls such as /api are resolved against it
+    #[clap(long, value_parser, default_value = "http://localhost")]
+    pub base: Url,
+}
+
+#[derive(Debug, Parser, Clone)]
+pub struct ImportPostmanArgs {
+    /// Postman collection, format v2.1
+    #[clap(value_parser)]
+    pub file: String,
+    /// Set or override a collection variable, e.g. `-v baseUrl=https://example.com`
+    #[clap(short, long, value_parser = parse_var, number_of_values=1)]
+    pub var: Vec<(String, String)>,
+}
+
+/// Send a profile repeatedly and summarize the status codes and latencies.
+#[derive(Debug, Parser, Clone)]
+pub struct LoadArgs {
+    /// profile name
+    #[clap(short, long, value_parser)]
+    pub profile: String,
+
+    /// Override args. Same as for `run`.
+    #[clap(short, long, value_parser = parse_key_val, number_of_values=1)]
+    pub extra_params: Vec<KeyVal>,
+    /// config file
+    #[clap(short, long, value_parser)]
+    pub config: Option<String>,
+    /// number of requests in flight at the same time
+    #[clap(short = 'n', long, value_parser, default_value = "10")]
+    pub concurrency: usize,
+    /// requests per second, unlimited when omitted
+    #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rate: Option<u32>,
+    /// how long to send requests, in seconds
+    #[clap(short, long, value_parser, default_value = "10")]
+    pub duration: u64,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -49,10 +202,24 @@ async fn main() -> Result<()> {
 
     let result = match args.action {
         Action::Run(args) => run(args).await,
-        Action::Parse(_) => parse().await,
+        Action::Parse(args) => parse(args).await,
+        Action::Snapshot(args) => snapshot(args).await,
+        Action::ParseCurl(args) => parse_curl(args),
+        Action::ExportCurl(args) => export_curl(args).await,
+        Action::ImportHar(args) => import_har(args).await,
+        Action::ImportOpenapi(args) => import_openapi(args).await,
+        Action::ImportPostman(args) => import_postman(args).await,
+        Action::Load(args) => load(args).await,
     };
+    let result = result.and(cookie::save_all().await);
+
+    let failed = result.is_err();
+    process_error_output(result)?;
+    if failed {
+        std::process::exit(1);
+    }
 
-    process_error_output(result)
+    Ok(())
 }
 
 async fn run(args: RunArgs) -> Result<()> {
@@ -65,12 +232,37 @@ async fn run(args: RunArgs) -> Result<()> {
             config_file
         )
     })?;
-    let extra_args = ExtraArgs::from(args.extra_params);
+    let extra_args = ExtraArgs::from(args.extra_params).with_cassette(args.cassette.load().await?);
 
-    let res: xdiff::ResponseExt = profile.request.send(&extra_args).await?;
+    if profile.matrix.is_none() {
+        return run_profile(profile, &extra_args).await;
+    }
 
-    let header_text = res.get_header_text(&profile.response.skip_headers)?;
-    let body_text = res.get_body_text(&profile.response.skip_body).await?;
+    let mut results = vec![];
+    for (name, case) in profile.cases().await? {
+        writeln!(stdout().lock(), "------\n# {}", name)?;
+        let result = run_profile(&case, &extra_args).await;
+        results.push(AssertionResult {
+            name,
+            passed: result.is_ok(),
+            message: result.err().map(|e| e.to_string()),
+        });
+    }
+
+    let failed = results.iter().filter(|r| !r.passed).count();
+    writeln!(stdout().lock(), "------\n{}", assertion_report(&results)?)?;
+    if failed > 0 {
+        return Err(anyhow!("{} of {} cases failed", failed, results.len()));
+    }
+
+    Ok(())
+}
+
+/// Send the request of a profile, print the response and check it.
+async fn run_profile(profile: &ReqProfile, extra_args: &ExtraArgs) -> Result<()> {
+    let res: xdiff::ResponseExt = profile.send(extra_args).await?;
+    let header_text = res.get_header_text(&profile.response)?;
+    let body_text = res.get_body_text(&profile.response.skip_body)?;
 
     let mut stdout = stdout().lock();
 
@@ -84,33 +276,41 @@ async fn run(args: RunArgs) -> Result<()> {
         writeln!(stdout, "{}", body_text)?;
     }
 
-    Ok(())
-}
+    let mut failed = 0;
+    if !profile.assertions.is_empty() {
+        let results = profile.assertions.check(&res)?;
+        writeln!(stdout, "------\n{}", assertion_report(&results)?)?;
+        failed += results.iter().filter(|r| !r.passed).count();
+    }
 
-async fn parse() -> Result<()> {
-    let theme = ColorfulTheme::default();
-    let url: String = Input::with_theme(&theme)
-        .with_prompt("Please enter url")
-        .interact_text()?;
+    let violations = profile.response.validate_schema(&res).await?;
+    if profile.response.schema.is_some() {
+        writeln!(stdout, "------\n{}", schema_report("response", &violations))?;
+    }
 
-    let profile_name: String = Input::with_theme(&theme)
-        .with_prompt("Please enter profile name")
-        .interact_text()?;
+    if failed > 0 {
+        return Err(anyhow!("{} assertions failed", failed));
+    }
+    if !violations.is_empty() {
+        return Err(anyhow!("{} schema violations", violations.len()));
+    }
 
-    let request: RequestProfile = url.parse()?;
+    Ok(())
+}
 
-    let res = request.send(&ExtraArgs::default()).await?;
+async fn parse(args: ParseArgs) -> Result<()> {
+    let interactive = args.url.is_none() || args.name.is_none();
+    let url = arg_or_prompt(args.url, "Please enter url")?;
+    let profile_name = arg_or_prompt(args.name, "Please enter profile name")?;
 
-    let header_options = res.get_headers();
-    let chosen = MultiSelect::with_theme(&theme)
-        .with_prompt("Choose headers to skip")
-        .items(&header_options)
-        .interact()?;
+    let request = args.request.build(&url)?;
 
-    let skip_headers = chosen
-        .iter()
-        .map(|&i| header_options[i].to_string())
-        .collect::<Vec<_>>();
+    let mut skip_headers = args.skip_header;
+    if interactive {
+        let res = request.send(&ExtraArgs::default()).await?;
+        let response = ResponseProfile::new(skip_headers.clone(), vec![]);
+        skip_headers.extend(select_skip_headers(&[&res], &response)?);
+    }
 
     let profile = ReqProfile::new(request, skip_headers);
 
@@ -118,8 +318,219 @@ async fn parse() -> Result<()> {
 
     let output = serde_yaml::to_string(&config)?;
 
+    let mut stdout = stdout().lock();
+    match args.append {
+        Some(path) => {
+            merge_config::<ReqConfig>(&path, &output, args.force).await?;
+            writeln!(stdout, "Profile added to {}", path)?;
+        }
+        None => writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?,
+    }
+
+    Ok(())
+}
+
+async fn snapshot(args: SnapshotArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "req.yaml".to_string());
+    let config = ReqConfig::load_yaml(&config_file).await?;
+    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+        anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+    let extra_args = ExtraArgs::from(args.extra_params).with_cassette(args.cassette.load().await?);
+
+    let mut results = vec![];
+    for (case, profile) in profile_cases(profile).await? {
+        let name = match &case {
+            Some(case) => format!("{}.{}", args.profile, case_slug(case)),
+            None => args.profile.clone(),
+        };
+        let path = Path::new(&args.dir).join(format!("{}.snap", name));
+        results.push((
+            name,
+            profile.snapshot(&path, &extra_args, args.update).await?,
+        ));
+    }
+
+    let mut stdout = stdout().lock();
+    for (name, status) in &results {
+        writeln!(stdout, "------\n{}", snapshot_report(name, status))?;
+    }
+
+    if results.iter().any(|(_, status)| status.is_changed()) {
+        return Err(anyhow!("snapshot changed, rerun with --update to accept"));
+    }
+
+    Ok(())
+}
+
+fn parse_curl(args: ParseCurlArgs) -> Result<()> {
+    let request = import::parse_curl(&arg_or_stdin(args.command)?)?;
+    let profile = ReqProfile::new(request, vec![]);
+    let config = ReqConfig::new(vec![(args.name, profile)].into_iter().collect());
+
+    let output = serde_yaml::to_string(&config)?;
+
+    let mut stdout = stdout().lock();
+    writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+
+    Ok(())
+}
+
+async fn export_curl(args: ExportCurlArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "req.yaml".to_string());
+    let config = ReqConfig::load_yaml(&config_file).await?;
+    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+        anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+    let extra_args = ExtraArgs::from(args.extra_params).with_cassette(args.cassette.load().await?);
+
+    let mut stdout = stdout().lock();
+    for (case, profile) in profile_cases(profile).await? {
+        let request = profile.resolve(&extra_args).await?;
+        let mut output = request.to_curl(&extra_args)?;
+        if let Some(case) = case {
+            output = format!("# {}\n{}", case, output);
+        }
+        writeln!(stdout, "{}", highlight(&output, "sh")?)?;
+    }
+
+    Ok(())
+}
+
+async fn import_har(args: ImportHarArgs) -> Result<()> {
+    let entries = import::load_har(&fs::read_to_string(&args.file).await?)?;
+    let entries = select_har_entries(entries, args.filter.as_deref())?;
+
+    let mut profiles = IndexMap::new();
+    let mut skipped = vec![];
+    for entry in entries {
+        let imported = entry.to_request().and_then(|request| {
+            let name = import::profile_name(&request.method, &entry.url()?, profiles.keys());
+            Ok((name, ReqProfile::new(request, vec![])))
+        });
+        match imported {
+            Ok((name, profile)) => {
+                profiles.insert(name, profile);
+            }
+            Err(e) => skipped.push((entry.request.url, e)),
+        }
+    }
+
+    let output = serde_yaml::to_string(&ReqConfig::new(profiles))?;
+
+    let mut stdout = stdout().lock();
+    writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+    if !skipped.is_empty() {
+        writeln!(stdout, "------\n{}", skipped_report(&skipped))?;
+    }
+
+    Ok(())
+}
+
+/// Operations which can not be converted are reported and left out.
+async fn import_openapi(args: ImportOpenapiArgs) -> Result<()> {
+    let spec = fs::read_to_string(&args.file)
+        .await?
+        .parse::<OpenApi>()?
+        .with_base(args.base.clone());
+    let server = match args.server {
+        Some(server) => server,
+        None => spec
+            .servers()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("No server found in {}, use --server", args.file))?,
+    };
+
+    let mut profiles = IndexMap::new();
+    let mut skipped = vec![];
+    for (name, request) in spec.requests(&server)? {
+        match request {
+            Ok(request) => {
+                profiles.insert(name, ReqProfile::new(request, vec![]));
+            }
+            Err(e) => skipped.push((name, e)),
+        }
+    }
+
+    let output = serde_yaml::to_string(&ReqConfig::new(profiles))?;
+
     let mut stdout = stdout().lock();
     writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+    if !skipped.is_empty() {
+        writeln!(stdout, "------\n{}", skipped_report(&skipped))?;
+    }
+
+    Ok(())
+}
+
+/// Requests which can not be converted are reported and left out.
+async fn import_postman(args: ImportPostmanArgs) -> Result<()> {
+    let collection: PostmanCollection = fs::read_to_string(&args.file).await?.parse()?;
+    let vars = args
+        .var
+        .into_iter()
+        .map(|(key, value)| (key, json!(value)))
+        .collect();
+
+    let mut profiles = IndexMap::new();
+    let mut skipped = vec![];
+    for (name, profile) in collection.profiles(&vars) {
+        match profile {
+            Ok(profile) => {
+                profiles.insert(name, profile);
+            }
+            Err(e) => skipped.push((name, e)),
+        }
+    }
+
+    let output = serde_yaml::to_string(&ReqConfig::new(profiles))?;
+
+    let mut stdout = stdout().lock();
+    writeln!(stdout, "------\n{}", highlight(&output, "yaml")?)?;
+    if !skipped.is_empty() {
+        writeln!(stdout, "------\n{}", skipped_report(&skipped))?;
+    }
+
+    Ok(())
+}
+
+async fn load(args: LoadArgs) -> Result<()> {
+    let config_file = args.config.unwrap_or_else(|| "req.yaml".to_string());
+    let config = ReqConfig::load_yaml(&config_file).await?;
+    let profile = config.get_profile(&args.profile).ok_or_else(|| {
+        anyhow!(
+            "Profile {} not found in config file {}",
+            args.profile,
+            config_file
+        )
+    })?;
+    if profile.matrix.is_some() {
+        return Err(anyhow!(
+            "Profile {} has a matrix, load tests need a single request",
+            args.profile
+        ));
+    }
+    let options = LoadOptions {
+        concurrency: args.concurrency,
+        rate: args.rate,
+        duration: Duration::from_secs(args.duration),
+    };
+
+    let report = profile
+        .load_test(ExtraArgs::from(args.extra_params), &options)
+        .await?;
+
+    let mut stdout = stdout().lock();
+    writeln!(stdout, "------\n{}", load_report(&report)?)?;
 
     Ok(())
 }
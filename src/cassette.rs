@@ -0,0 +1,348 @@
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Request, StatusCode, Version,
+};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::ResponseExt;
+
+/// Whether a cassette records live traffic or answers requests from its recordings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+/// A file of recorded request/response pairs, used to run profiles without network access.
+#[derive(Debug, Clone)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    state: Arc<Mutex<CassetteState>>,
+    /// held from taking a copy of the interactions until it is written, so an older copy can
+    /// not overwrite a newer one
+    writing: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Debug, Default)]
+struct CassetteState {
+    interactions: Vec<Interaction>,
+    /// Replay: interactions already answered. Record: requests recorded during this run.
+    used: HashSet<usize>,
+    recorded: HashSet<RecordedRequest>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+/// The parts of a request a recording is matched on.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub query: Vec<Pair>,
+    #[serde(skip_serializing_if = "String::is_empty", default)]
+    pub body: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pair {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    pub version: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub headers: Vec<Pair>,
+    pub body: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub ttfb_ms: Option<u64>,
+    pub elapsed_ms: u64,
+}
+
+impl Cassette {
+    /// Open the cassette at `path`. Recording keeps interactions from earlier runs, except the
+    /// ones for requests that get recorded again.
+    pub async fn load(path: impl Into<PathBuf>, mode: CassetteMode) -> Result<Self> {
+        let path = path.into();
+        let interactions = match fs::read_to_string(&path).await {
+            Ok(content) => serde_yaml::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && mode == CassetteMode::Record => {
+                vec![]
+            }
+            Err(e) => return Err(anyhow!("failed to open cassette {}: {}", path.display(), e)),
+        };
+
+        Ok(Self {
+            path,
+            mode,
+            state: Arc::new(Mutex::new(CassetteState {
+                interactions,
+                ..Default::default()
+            })),
+            writing: Arc::new(tokio::sync::Mutex::new(())),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    /// Find the recorded response for `request`. Identical requests are answered in the order
+    /// they were recorded, and the last one is repeated once they are used up.
+    pub fn replay(&self, request: &RecordedRequest) -> Result<ResponseExt> {
+        let mut state = self.lock()?;
+        let matches = state
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, i)| &i.request == request)
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+
+        let idx = matches
+            .iter()
+            .find(|idx| !state.used.contains(idx))
+            .or_else(|| matches.last())
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "no recorded response for {} {} in cassette {}",
+                    request.method,
+                    request.url,
+                    self.path.display()
+                )
+            })?;
+        state.used.insert(idx);
+
+        state.interactions[idx].response.to_response()
+    }
+
+    pub async fn record(&self, request: RecordedRequest, res: &ResponseExt) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let content = {
+            let mut state = self.lock()?;
+            if state.recorded.insert(request.clone()) {
+                state.interactions.retain(|i| i.request != request);
+            }
+            state.interactions.push(Interaction {
+                request,
+                response: RecordedResponse::from_response(res),
+            });
+            serde_yaml::to_string(&state.interactions)?
+        };
+
+        fs::write(&self.path, content).await?;
+
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, CassetteState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("cassette lock poisoned"))
+    }
+}
+
+impl RecordedRequest {
+    pub fn from_request(request: &Request) -> Self {
+        let mut url = request.url().clone();
+        let mut query = url
+            .query_pairs()
+            .map(|(k, v)| Pair::new(k, v))
+            .collect::<Vec<_>>();
+        query.sort();
+        url.set_query(None);
+
+        let body = request
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map(|b| String::from_utf8_lossy(b).to_string())
+            .unwrap_or_default();
+
+        Self {
+            method: request.method().to_string(),
+            url: url.to_string(),
+            query,
+            body,
+        }
+    }
+}
+
+impl Pair {
+    fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl RecordedResponse {
+    fn from_response(res: &ResponseExt) -> Self {
+        Self {
+            status: res.status().as_u16(),
+            version: format!("{:?}", res.version()),
+            headers: res
+                .headers()
+                .iter()
+                .map(|(k, v)| Pair::new(k.as_str(), String::from_utf8_lossy(v.as_bytes())))
+                .collect(),
+            body: res.body().to_string(),
+            ttfb_ms: Some(res.ttfb().as_millis() as u64),
+            elapsed_ms: res.elapsed().as_millis() as u64,
+        }
+    }
+
+    fn to_response(&self) -> Result<ResponseExt> {
+        let version = match self.version.as_str() {
+            "HTTP/0.9" => Version::HTTP_09,
+            "HTTP/1.0" => Version::HTTP_10,
+            "HTTP/2.0" => Version::HTTP_2,
+            "HTTP/3.0" => Version::HTTP_3,
+            _ => Version::HTTP_11,
+        };
+
+        let mut headers = HeaderMap::new();
+        for Pair { name, value } in &self.headers {
+            headers.append(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+        }
+
+        let res = ResponseExt::new(
+            version,
+            StatusCode::from_u16(self.status)?,
+            headers,
+            self.body.clone(),
+            Duration::from_millis(self.elapsed_ms),
+        );
+
+        Ok(match self.ttfb_ms {
+            Some(ttfb) => res.with_ttfb(Duration::from_millis(ttfb)),
+            None => res,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server::serve, DiffConfig, ExtraArgs, LoadYaml, RequestProfile};
+    use futures::future::try_join_all;
+    use hyper::{Body, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn record_then_replay_round_trip() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let addr = serve(move |parts, _| {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            Response::builder()
+                .status(if parts.uri.path() == "/missing" {
+                    404
+                } else {
+                    200
+                })
+                .header("x-call", n.to_string())
+                .body(Body::from(format!("{} #{}", parts.uri, n)))
+                .unwrap()
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.yml");
+        let requests = (0..50)
+            .map(|i| format!("http://{}/items/{}?q=1", addr, i).parse())
+            .chain([format!("http://{}/missing", addr).parse()])
+            .collect::<Result<Vec<RequestProfile>>>()
+            .unwrap();
+
+        let cassette = Cassette::load(&path, CassetteMode::Record).await.unwrap();
+        let extra = ExtraArgs::default().with_cassette(Some(cassette));
+        let recorded = try_join_all(requests.iter().map(|r| r.send(&extra)))
+            .await
+            .unwrap();
+
+        let cassette = Cassette::load(&path, CassetteMode::Replay).await.unwrap();
+        let extra = ExtraArgs::default().with_cassette(Some(cassette));
+        for (request, recorded) in requests.iter().zip(&recorded) {
+            let replayed = request.send(&extra).await.unwrap();
+            assert_eq!(replayed.status(), recorded.status());
+            assert_eq!(replayed.body(), recorded.body());
+            assert_eq!(replayed.headers()["x-call"], recorded.headers()["x-call"]);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), requests.len());
+    }
+
+    #[tokio::test]
+    async fn identical_requests_replay_in_order() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        let addr = serve(move |_, _| {
+            Response::new(Body::from(
+                counter.fetch_add(1, Ordering::SeqCst).to_string(),
+            ))
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.yml");
+        let request: RequestProfile = format!("http://{}/next", addr).parse().unwrap();
+
+        let cassette = Cassette::load(&path, CassetteMode::Record).await.unwrap();
+        let extra = ExtraArgs::default().with_cassette(Some(cassette));
+        for _ in 0..2 {
+            request.send(&extra).await.unwrap();
+        }
+
+        let cassette = Cassette::load(&path, CassetteMode::Replay).await.unwrap();
+        let extra = ExtraArgs::default().with_cassette(Some(cassette));
+        let mut bodies = vec![];
+        for _ in 0..3 {
+            bodies.push(request.send(&extra).await.unwrap().body().to_string());
+        }
+        assert_eq!(bodies, ["0", "1", "1"]);
+    }
+
+    #[tokio::test]
+    async fn replay_without_recording_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cassette.yml");
+        assert!(Cassette::load(&path, CassetteMode::Replay).await.is_err());
+
+        fs::write(&path, "[]").await.unwrap();
+        let cassette = Cassette::load(&path, CassetteMode::Replay).await.unwrap();
+        let extra = ExtraArgs::default().with_cassette(Some(cassette));
+        let request: RequestProfile = "http://127.0.0.1:1/".parse().unwrap();
+        assert!(request.send(&extra).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fixture_cassette_replays_the_todo_profile() {
+        let config = DiffConfig::load_yaml("fixtures/test.yml").await.unwrap();
+        let profile = config.get_profile("todo").unwrap();
+        let cassette = Cassette::load("fixtures/cassette.yml", CassetteMode::Replay)
+            .await
+            .unwrap();
+        let extra = ExtraArgs::default().with_cassette(Some(cassette));
+
+        let report = profile.diff(&extra, 1).await.unwrap();
+        assert!(report.changed);
+        let diff = &report.diffs["request2"];
+        assert!(diff.status.is_none() && diff.headers.iter().all(|h| h.name == "date"));
+        assert!(diff.body.as_ref().unwrap().contains("quis ut nam facilis"));
+    }
+}
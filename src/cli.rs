@@ -1,6 +1,25 @@
+use std::{io::Write, ops::Range, path::Path, str::FromStr, sync::OnceLock};
+
 use anyhow::{anyhow, Result};
+use atty::Stream;
+use clap::Parser;
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect};
+use indexmap::IndexMap;
+use regex::Regex;
+use reqwest::{
+    header::{HeaderName, HeaderValue},
+    Method,
+};
+use serde_json::Value;
+use tempfile::NamedTempFile;
+use tokio::fs;
 
-use crate::ExtraArgs;
+use crate::{
+    cassette::{Cassette, CassetteMode},
+    import::HarEntry,
+    json_path, DiffProfile, ExtraArgs, LoadYaml, ReqProfile, RequestProfile, ResponseExt,
+    ResponseProfile,
+};
 
 /// key value pair
 #[derive(Debug, Clone)]
@@ -58,6 +77,603 @@ impl From<Vec<KeyVal>> for ExtraArgs {
             headers,
             body,
             query,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parse a plain `key=value` pair.
+pub fn parse_var(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Invalid key value pair: {}", s))?;
+    Ok((key.trim().to_string(), value.trim().to_string()))
+}
+
+/// Return `arg`, or read all of stdin when it is not given.
+pub fn arg_or_stdin(arg: Option<String>) -> Result<String> {
+    match arg {
+        Some(v) => Ok(v),
+        None => Ok(std::io::read_to_string(std::io::stdin())?),
+    }
+}
+
+/// Return `arg`, or ask the user for it.
+pub fn arg_or_prompt(arg: Option<String>, prompt: &str) -> Result<String> {
+    match arg {
+        Some(v) => Ok(v),
+        None => Ok(Input::with_theme(&ColorfulTheme::default())
+            .with_prompt(prompt)
+            .interact_text()?),
+    }
+}
+
+/// Add the profiles in `yaml` to the config file at `path`, creating it if needed. A profile
+/// whose name is taken is only replaced, in place, with `force` or when the user agrees. The
+/// result is validated and written to a temporary file first, so the config is never left
+/// half written.
+pub async fn merge_config<T: LoadYaml>(path: &str, yaml: &str, force: bool) -> Result<()> {
+    let content = match fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(anyhow!("failed to read {}: {}", path, e)),
+    };
+    let mut profiles: IndexMap<String, serde_yaml::Value> = match content.trim() {
+        "" => IndexMap::new(),
+        _ => serde_yaml::from_str(&content)?,
+    };
+    let new: IndexMap<String, serde_yaml::Value> = serde_yaml::from_str(yaml)?;
+
+    let mut replaced = false;
+    for name in new.keys().filter(|name| profiles.contains_key(*name)) {
+        let agreed = !force
+            && atty::is(Stream::Stdin)
+            && Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!(
+                    "Profile {} already exists in {}, replace it?",
+                    name, path
+                ))
+                .default(false)
+                .interact()?;
+        if !force && !agreed {
+            return Err(anyhow!(
+                "Profile {} already exists in {}, use --force to replace it",
+                name,
+                path
+            ));
+        }
+        replaced = true;
+    }
+
+    // splicing the new profiles into the text keeps the comments and formatting of the file
+    let output = match replaced {
+        true => match splice_profiles(&content, yaml)? {
+            Some(output) => output,
+            None => {
+                profiles.extend(new);
+                serde_yaml::to_string(&profiles)?
+            }
+        },
+        false if content.is_empty() || content.ends_with('\n') => format!("{}{}", content, yaml),
+        false => format!("{}\n{}", content, yaml),
+    };
+    T::from_yaml(&output)?;
+
+    let path = Path::new(path).to_owned();
+    tokio::task::spawn_blocking(move || write_atomically(&path, &output)).await?
+}
+
+/// Write `content` to a temporary file next to `path`, sync it and move it over `path`. The
+/// temporary file is removed when any step fails.
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut tmp = NamedTempFile::new_in(dir)?;
+    tmp.write_all(content.as_bytes())?;
+    if let Ok(metadata) = std::fs::metadata(path) {
+        tmp.as_file().set_permissions(metadata.permissions())?;
+    }
+    tmp.as_file().sync_all()?;
+    tmp.persist(path)?;
+
+    Ok(())
+}
+
+/// Replace the top level entries of `content` with those of the same name in `yaml`, appending
+/// the rest, and leave every other line alone. Returns None when the file's layout can't be
+/// spliced safely (e.g. flow style or anchors), so the caller can fall back to re-serializing.
+fn splice_profiles(content: &str, yaml: &str) -> Result<Option<String>> {
+    let mut expected: IndexMap<String, serde_yaml::Value> = serde_yaml::from_str(content)?;
+    expected.extend(serde_yaml::from_str::<IndexMap<String, serde_yaml::Value>>(
+        yaml,
+    )?);
+
+    let existing = top_level_entries(content);
+    let mut output = content.to_string();
+    let mut appended = String::new();
+    let mut replacements = vec![];
+    for (name, range) in top_level_entries(yaml) {
+        let entry = format!("{}\n", yaml[range].trim_end_matches('\n'));
+        match existing.get(&name) {
+            Some(range) => replacements.push((range.clone(), entry)),
+            None => appended.push_str(&entry),
+        }
+    }
+    replacements.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    for (range, entry) in replacements {
+        output.replace_range(range, &entry);
+    }
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(&appended);
+
+    let spliced: Option<IndexMap<String, serde_yaml::Value>> = serde_yaml::from_str(&output).ok();
+    Ok((spliced == Some(expected)).then_some(output))
+}
+
+/// The byte ranges of the top level entries of a yaml mapping by name. An entry runs from its
+/// key line up to the next line starting at column 0, without the blank lines before it, so
+/// comments between entries stay where they are.
+fn top_level_entries(text: &str) -> IndexMap<String, Range<usize>> {
+    let mut entries = IndexMap::new();
+    let mut current: Option<(String, Range<usize>)> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, range)) = current.as_mut() {
+                range.end = offset;
+            }
+            continue;
+        }
+        entries.extend(current.take());
+        if let Some(key) = top_level_key(line) {
+            current = Some((key, start..offset));
+        }
+    }
+    entries.extend(current);
+    entries
+}
+
+fn top_level_key(line: &str) -> Option<String> {
+    if line.starts_with(['#', '-', '{', '[', '&', '*', '?']) || line.starts_with("...") {
+        return None;
+    }
+    let after_quote = match line.chars().next() {
+        Some(q @ ('"' | '\'')) => line[1..].find(q)? + 2,
+        _ => 0,
+    };
+    let colon = line[after_quote..]
+        .match_indices(':')
+        .map(|(i, _)| after_quote + i)
+        .find(|i| line[i + 1..].starts_with([' ', '\t', '\r', '\n']) || line.len() == i + 1)?;
+    serde_yaml::from_str(line[..colon].trim()).ok()
+}
+
+/// Let the user choose which response headers to skip. Headers the profile does not compare
+/// anyway, given its `only_headers` and `skip_headers`, are not offered.
+pub fn select_skip_headers(
+    responses: &[&ResponseExt],
+    response: &ResponseProfile,
+) -> Result<Vec<String>> {
+    let names = header_candidates(responses, response)?;
+    if names.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Choose headers to skip")
+        .items(&names)
+        .interact()?;
+
+    Ok(chosen.into_iter().map(|i| names[i].clone()).collect())
+}
+
+fn header_candidates(
+    responses: &[&ResponseExt],
+    response: &ResponseProfile,
+) -> Result<Vec<String>> {
+    let mut names = vec![];
+    for name in responses.iter().flat_map(|res| res.get_headers()) {
+        if !names.contains(&name) && response.compares_header(&name)? {
+            names.push(name);
         }
     }
+    names.sort();
+    Ok(names)
+}
+
+/// Let the user choose which of the body paths that differ between two json responses to skip.
+/// Paths that look like timestamps or ids are selected up front, paths under the `skip_body` of
+/// the profile are not offered.
+pub fn select_skip_body(
+    body1: &str,
+    body2: &str,
+    response: &ResponseProfile,
+) -> Result<Vec<String>> {
+    let candidates = body_candidates(body1, body2, response)?;
+    if candidates.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let (paths, defaults): (Vec<_>, Vec<_>) = candidates.into_iter().unzip();
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Choose body fields to skip")
+        .items(&paths)
+        .defaults(&defaults)
+        .interact()?;
+
+    Ok(chosen.into_iter().map(|i| paths[i].clone()).collect())
+}
+
+/// The paths which differ between the bodies, and whether they look volatile.
+fn body_candidates(
+    body1: &str,
+    body2: &str,
+    response: &ResponseProfile,
+) -> Result<Vec<(String, bool)>> {
+    let (Ok(mut body1), Ok(mut body2)) = (
+        serde_json::from_str::<Value>(body1),
+        serde_json::from_str::<Value>(body2),
+    ) else {
+        return Ok(vec![]);
+    };
+    for path in &response.skip_body {
+        json_path::remove(&mut body1, path)?;
+        json_path::remove(&mut body2, path)?;
+    }
+
+    Ok(json_path::diff_paths(&body1, &body2)
+        .into_iter()
+        .map(|path| {
+            let volatile = is_volatile(&path, json_path::get(&body1, &path).ok().flatten());
+            (path, volatile)
+        })
+        .collect())
+}
+
+fn is_volatile(path: &str, value: Option<&Value>) -> bool {
+    static TIMESTAMP: OnceLock<Regex> = OnceLock::new();
+    static UUID: OnceLock<Regex> = OnceLock::new();
+
+    let name = path
+        .rsplit(['.', '['])
+        .next()
+        .unwrap_or(path)
+        .trim_end_matches([']', '\''])
+        .trim_start_matches('\'');
+    // the last word of snake_case, kebab-case or camelCase names, so `created_at`, `createdAt`
+    // and `requestID` match while `candidate` does not
+    let chars = name.char_indices().collect::<Vec<_>>();
+    let start = chars
+        .windows(2)
+        .rfind(|w| matches!(w[0].1, '_' | '-') || w[0].1.is_lowercase() && w[1].1.is_uppercase())
+        .map_or(0, |w| w[1].0);
+    let word = name[start..].to_lowercase();
+    let volatile_name = [
+        "id",
+        "uuid",
+        "guid",
+        "ts",
+        "at",
+        "now",
+        "nonce",
+        "etag",
+        "time",
+        "date",
+        "timestamp",
+        "trace",
+        "request",
+    ]
+    .contains(&word.as_str());
+
+    let timestamp =
+        TIMESTAMP.get_or_init(|| Regex::new(r"^\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}").unwrap());
+    let uuid = UUID.get_or_init(|| {
+        Regex::new(r"^[0-9a-fA-F]{8}-([0-9a-fA-F]{4}-){3}[0-9a-fA-F]{12}$").unwrap()
+    });
+    let volatile_value = match value {
+        // seconds or milliseconds since the epoch
+        Some(Value::Number(n)) => n.as_f64().is_some_and(|n| n > 1e9),
+        Some(Value::String(s)) => timestamp.is_match(s) || uuid.is_match(s),
+        _ => false,
+    };
+
+    volatile_name || volatile_value
+}
+
+/// Pick the HAR entries whose url matches `filter`, or let the user choose them.
+pub fn select_har_entries(entries: Vec<HarEntry>, filter: Option<&str>) -> Result<Vec<HarEntry>> {
+    if let Some(filter) = filter {
+        let re = Regex::new(filter)?;
+        return Ok(entries
+            .into_iter()
+            .filter(|e| re.is_match(&e.request.url))
+            .collect());
+    }
+
+    let items = entries
+        .iter()
+        .map(|e| format!("{} {}", e.request.method, e.request.url))
+        .collect::<Vec<_>>();
+    let chosen = MultiSelect::with_theme(&ColorfulTheme::default())
+        .with_prompt("Choose requests to import")
+        .items(&items)
+        .interact()?;
+
+    Ok(chosen.into_iter().map(|i| entries[i].clone()).collect())
+}
+
+/// Profiles which may expand into the cases of a matrix.
+#[allow(async_fn_in_trait)]
+pub trait MatrixCases: Clone {
+    fn has_matrix(&self) -> bool;
+    async fn matrix_cases(&self) -> Result<Vec<(String, Self)>>;
+}
+
+impl MatrixCases for DiffProfile {
+    fn has_matrix(&self) -> bool {
+        self.matrix.is_some()
+    }
+
+    async fn matrix_cases(&self) -> Result<Vec<(String, Self)>> {
+        self.cases().await
+    }
+}
+
+impl MatrixCases for ReqProfile {
+    fn has_matrix(&self) -> bool {
+        self.matrix.is_some()
+    }
+
+    async fn matrix_cases(&self) -> Result<Vec<(String, Self)>> {
+        self.cases().await
+    }
+}
+
+/// The cases of a matrix profile by name, or the profile itself as the only, unnamed case.
+pub async fn profile_cases<P: MatrixCases>(profile: &P) -> Result<Vec<(Option<String>, P)>> {
+    if !profile.has_matrix() {
+        return Ok(vec![(None, profile.clone())]);
+    }
+
+    Ok(profile
+        .matrix_cases()
+        .await?
+        .into_iter()
+        .map(|(name, case)| (Some(name), case))
+        .collect())
+}
+
+// Options shared by the subcommands that send requests. Not a doc comment, as clap would
+// show it as the help of every subcommand it is flattened into.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct CassetteArgs {
+    /// Replay responses from this cassette file instead of sending requests
+    #[clap(long, value_parser)]
+    pub cassette: Option<String>,
+    /// Send the requests and record them into the cassette
+    #[clap(long, requires = "cassette")]
+    pub record: bool,
+}
+
+impl CassetteArgs {
+    pub async fn load(&self) -> Result<Option<Cassette>> {
+        let mode = if self.record {
+            CassetteMode::Record
+        } else {
+            CassetteMode::Replay
+        };
+
+        match &self.cassette {
+            Some(path) => Ok(Some(Cassette::load(path, mode).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Options for the requests generated by `parse`.
+#[derive(Parser, Debug, Clone, Default)]
+pub struct RequestArgs {
+    /// HTTP method of the request
+    #[clap(short = 'X', long, value_parser, default_value = "GET")]
+    pub method: String,
+    /// Request header, e.g. `-H accept=application/json`
+    #[clap(short = 'H', long, value_parser = parse_var, number_of_values = 1)]
+    pub header: Vec<(String, String)>,
+}
+
+impl RequestArgs {
+    pub fn build(&self, url: &str) -> Result<RequestProfile> {
+        let mut request: RequestProfile = url.parse()?;
+        request.method = Method::from_str(&self.method.to_uppercase())?;
+        for (key, value) in &self.header {
+            request
+                .headers
+                .append(HeaderName::from_str(key)?, HeaderValue::from_str(value)?);
+        }
+
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ReqConfig;
+    use serde_json::json;
+
+    #[test]
+    fn key_vals_are_sorted_into_extra_args() {
+        let kvs = ["a=1", "%x-token = t", "@name=n=1"]
+            .iter()
+            .map(|s| parse_key_val(s))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let extra = ExtraArgs::from(kvs);
+
+        assert_eq!(extra.query, [("a".to_string(), "1".to_string())]);
+        assert_eq!(extra.headers, [("x-token".to_string(), "t".to_string())]);
+        assert_eq!(extra.body, [("name".to_string(), "n=1".to_string())]);
+        assert!(parse_key_val("novalue").is_err());
+        assert!(parse_key_val("1a=b").is_err());
+    }
+
+    #[test]
+    fn request_args_build_the_request() {
+        let args = RequestArgs {
+            method: "post".to_string(),
+            header: vec![parse_var("accept = application/json").unwrap()],
+        };
+        let request = args.build("https://example.com/todos?a=1").unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url.as_str(), "https://example.com/todos");
+        assert_eq!(request.params, Some(json!({"a": "1"})));
+        assert_eq!(request.headers["accept"], "application/json");
+        assert!(parse_var("novalue").is_err());
+    }
+
+    #[tokio::test]
+    async fn merge_config_splices_profiles_into_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("xreq.yml");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "# todos\ntodo:\n  request:\n    url: https://a.com/1\n\n# users\nuser:\n  request:\n    url: https://a.com/u # inline\n",
+        )
+        .unwrap();
+
+        let yaml = "todo:\n  request:\n    url: https://b.com/1\nnew:\n  request:\n    url: https://b.com/n\n";
+        merge_config::<ReqConfig>(path, yaml, true).await.unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "# todos\ntodo:\n  request:\n    url: https://b.com/1\n\n# users\nuser:\n  request:\n    url: https://a.com/u # inline\nnew:\n  request:\n    url: https://b.com/n\n"
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let yaml = "other:\n  request:\n    url: https://b.com/o\n";
+        merge_config::<ReqConfig>(path, yaml, false).await.unwrap();
+        assert!(std::fs::read_to_string(path).unwrap().ends_with(yaml));
+
+        // an invalid result leaves the file and the directory as they were
+        let before = std::fs::read_to_string(path).unwrap();
+        let yaml = "broken:\n  request:\n    url: not a url\n";
+        assert!(merge_config::<ReqConfig>(path, yaml, true).await.is_err());
+        assert_eq!(std::fs::read_to_string(path).unwrap(), before);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn profile_cases_expand_only_matrix_profiles() {
+        let config = ReqConfig::from_yaml(
+            r#"
+plain:
+  request:
+    url: https://example.com/todos/1
+matrix:
+  matrix:
+    values:
+      - id: 1
+      - id: 2
+  request:
+    url: https://example.com/todos/{{id}}
+"#,
+        )
+        .unwrap();
+
+        let cases = profile_cases(config.get_profile("plain").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(cases[0].0, None);
+
+        let cases = profile_cases(config.get_profile("matrix").unwrap())
+            .await
+            .unwrap();
+        let names: Vec<_> = cases.iter().map(|(name, _)| name.as_deref()).collect();
+        assert_eq!(names, [Some("id=1"), Some("id=2")]);
+        assert!(cases.iter().all(|(_, case)| case.matrix.is_none()));
+    }
+
+    #[test]
+    fn splice_falls_back_when_the_layout_is_unknown() {
+        let yaml = "todo:\n  request:\n    url: https://b.com/1\n";
+        let flow = "{todo: {request: {url: 'https://a.com/1'}}}\n";
+        assert_eq!(splice_profiles(flow, yaml).unwrap(), None);
+
+        let quoted = "\"to do\": # the first\n  request:\n    url: https://a.com/1\n";
+        let yaml = "to do:\n  request:\n    url: https://b.com/1\n";
+        assert_eq!(
+            splice_profiles(quoted, yaml).unwrap(),
+            Some(yaml.to_string())
+        );
+
+        assert_eq!(top_level_key("'a: b': 1\n"), Some("a: b".to_string()));
+        assert_eq!(top_level_key("url:http\n"), None);
+        assert_eq!(top_level_key("# a: b\n"), None);
+    }
+
+    #[tokio::test]
+    async fn candidates_leave_out_what_the_profile_skips() {
+        let addr = crate::test_server::serve(|_, _| {
+            hyper::Response::builder()
+                .header("content-type", "application/json")
+                .header("x-request-id", "1")
+                .header("x-trace", "2")
+                .body(hyper::Body::from("{}"))
+                .unwrap()
+        });
+        let request: RequestProfile = format!("http://{}/", addr).parse().unwrap();
+        let res = request.send(&ExtraArgs::default()).await.unwrap();
+
+        let mut response = ResponseProfile::new(vec!["x-*".to_string()], vec![]);
+        let names = header_candidates(&[&res, &res], &response).unwrap();
+        assert!(names.contains(&"content-type".to_string()));
+        assert!(!names.iter().any(|name| name.starts_with("x-")));
+        response.only_headers = vec!["content-type".to_string()];
+        let names = header_candidates(&[&res], &response).unwrap();
+        assert_eq!(names, ["content-type"]);
+
+        let body1 = r#"{"id": 1, "meta": {"ts": 1}, "name": "a"}"#;
+        let body2 = r#"{"id": 2, "meta": {"ts": 2}, "name": "b"}"#;
+        let response = ResponseProfile::new(vec![], vec!["meta".to_string()]);
+        assert_eq!(
+            body_candidates(body1, body2, &response).unwrap(),
+            [("id".to_string(), true), ("name".to_string(), false)]
+        );
+    }
+
+    #[test]
+    fn volatile_fields_match_by_name_or_value() {
+        for path in [
+            "id",
+            "data.created_at",
+            "items[*].createdAt",
+            "requestID",
+            "['x-trace']",
+        ] {
+            assert!(is_volatile(path, None), "{}", path);
+        }
+        for path in ["candidate", "validate", "runtime", "data.name", "$"] {
+            assert!(!is_volatile(path, None), "{}", path);
+        }
+
+        assert!(is_volatile("a", Some(&json!(1700000000))));
+        assert!(is_volatile("a", Some(&json!("2024-01-02T03:04:05Z"))));
+        assert!(is_volatile(
+            "a",
+            Some(&json!("123e4567-e89b-12d3-a456-426614174000"))
+        ));
+        assert!(!is_volatile("a", Some(&json!("hello"))));
+    }
 }
@@ -0,0 +1,373 @@
+use std::fmt;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{json_path, ResponseExt, ValidateConfig};
+
+/// Checks run against the response of a `ReqProfile`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct Assertions {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub status: Option<u16>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub headers: Vec<HeaderAssertion>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub json: Vec<JsonAssertion>,
+    /// Upper bound for the total response time, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub max_time_ms: Option<u64>,
+}
+
+/// Asserts a header is present (or absent), optionally with an exact or matching value.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct HeaderAssertion {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub exists: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub eq: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub regex: Option<String>,
+}
+
+/// Asserts on the value at a JSON path of the response body.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct JsonAssertion {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub exists: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub eq: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub regex: Option<String>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none", default)]
+    pub json_type: Option<JsonType>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JsonType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+    Null,
+}
+
+/// The outcome of a single assertion.
+#[derive(Debug, Clone)]
+pub struct AssertionResult {
+    pub name: String,
+    pub passed: bool,
+    pub message: Option<String>,
+}
+
+impl ValidateConfig for Assertions {
+    fn validate(&self) -> Result<()> {
+        for header in &self.headers {
+            if let Some(re) = &header.regex {
+                Regex::new(re).context(format!("invalid regex for header {}", header.name))?;
+            }
+        }
+
+        for json in &self.json {
+            json_path::parse(&json.path)?;
+            if let Some(re) = &json.regex {
+                Regex::new(re).context(format!("invalid regex for {}", json.path))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Assertions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn check(&self, res: &ResponseExt) -> Result<Vec<AssertionResult>> {
+        let mut results = vec![];
+
+        if let Some(status) = self.status {
+            let actual = res.status().as_u16();
+            results.push(AssertionResult::new(
+                format!("status == {}", status),
+                actual == status,
+                format!("got {}", actual),
+            ));
+        }
+
+        for header in &self.headers {
+            results.extend(header.check(res)?);
+        }
+
+        if !self.json.is_empty() {
+            let body: Value =
+                serde_json::from_str(res.body()).context("response body is not valid json")?;
+            for json in &self.json {
+                results.extend(json.check(&body)?);
+            }
+        }
+
+        if let Some(max) = self.max_time_ms {
+            let actual = res.elapsed().as_millis();
+            results.push(AssertionResult::new(
+                format!("time <= {}ms", max),
+                actual <= max as u128,
+                format!("took {}ms", actual),
+            ));
+        }
+
+        Ok(results)
+    }
+}
+
+impl HeaderAssertion {
+    fn check(&self, res: &ResponseExt) -> Result<Vec<AssertionResult>> {
+        let mut results = vec![];
+        let values = res.header_values(&self.name);
+
+        // presence is implied when a value is checked
+        let exists = self
+            .exists
+            .unwrap_or(self.eq.is_none() && self.regex.is_none());
+        if exists || self.exists.is_some() {
+            results.push(AssertionResult::new(
+                format!(
+                    "header {} {}",
+                    self.name,
+                    if exists { "exists" } else { "is absent" }
+                ),
+                values.is_empty() != exists,
+                format!("got {:?}", values),
+            ));
+        }
+
+        if let Some(eq) = &self.eq {
+            results.push(AssertionResult::new(
+                format!("header {} == {:?}", self.name, eq),
+                values.contains(eq),
+                format!("got {:?}", values),
+            ));
+        }
+
+        if let Some(re) = &self.regex {
+            let regex = Regex::new(re)?;
+            results.push(AssertionResult::new(
+                format!("header {} =~ /{}/", self.name, re),
+                values.iter().any(|v| regex.is_match(v)),
+                format!("got {:?}", values),
+            ));
+        }
+
+        Ok(results)
+    }
+}
+
+impl JsonAssertion {
+    fn check(&self, body: &Value) -> Result<Vec<AssertionResult>> {
+        let mut results = vec![];
+        let value = json_path::get(body, &self.path)?;
+        let got = || match value {
+            Some(v) => format!("got {}", v),
+            None => "not found".to_string(),
+        };
+
+        let exists = self
+            .exists
+            .unwrap_or(self.eq.is_none() && self.regex.is_none() && self.json_type.is_none());
+        if exists || self.exists.is_some() {
+            results.push(AssertionResult::new(
+                format!(
+                    "{} {}",
+                    self.path,
+                    if exists { "exists" } else { "is absent" }
+                ),
+                value.is_some() == exists,
+                got(),
+            ));
+        }
+
+        if let Some(eq) = &self.eq {
+            results.push(AssertionResult::new(
+                format!("{} == {}", self.path, eq),
+                value == Some(eq),
+                got(),
+            ));
+        }
+
+        if let Some(re) = &self.regex {
+            let regex = Regex::new(re)?;
+            let passed = match value {
+                Some(Value::String(v)) => regex.is_match(v),
+                Some(v) => regex.is_match(&v.to_string()),
+                None => false,
+            };
+            results.push(AssertionResult::new(
+                format!("{} =~ /{}/", self.path, re),
+                passed,
+                got(),
+            ));
+        }
+
+        if let Some(json_type) = self.json_type {
+            results.push(AssertionResult::new(
+                format!("{} is {}", self.path, json_type),
+                value.is_some_and(|v| json_type.matches(v)),
+                got(),
+            ));
+        }
+
+        Ok(results)
+    }
+}
+
+impl JsonType {
+    pub fn matches(&self, value: &Value) -> bool {
+        match self {
+            JsonType::String => value.is_string(),
+            JsonType::Number => value.is_number(),
+            JsonType::Integer => value.is_i64() || value.is_u64(),
+            JsonType::Boolean => value.is_boolean(),
+            JsonType::Array => value.is_array(),
+            JsonType::Object => value.is_object(),
+            JsonType::Null => value.is_null(),
+        }
+    }
+}
+
+impl fmt::Display for JsonType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            JsonType::String => "string",
+            JsonType::Number => "number",
+            JsonType::Integer => "integer",
+            JsonType::Boolean => "boolean",
+            JsonType::Array => "array",
+            JsonType::Object => "object",
+            JsonType::Null => "null",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl AssertionResult {
+    fn new(name: String, passed: bool, message: String) -> Self {
+        Self {
+            name,
+            passed,
+            message: (!passed).then_some(message),
+        }
+    }
+}
+
+impl fmt::Display for AssertionResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.message, self.passed) {
+            (_, true) => write!(f, "PASS {}", self.name),
+            (Some(message), false) => write!(f, "FAIL {} ({})", self.name, message),
+            (None, false) => write!(f, "FAIL {}", self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue},
+        StatusCode, Version,
+    };
+    use std::time::Duration;
+
+    fn response() -> ResponseExt {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers.append("set-cookie", HeaderValue::from_static("a=1"));
+        headers.append("set-cookie", HeaderValue::from_static("b=2"));
+        ResponseExt::new(
+            Version::HTTP_11,
+            StatusCode::OK,
+            headers,
+            r#"{"id": 7, "name": "todo", "tags": []}"#.to_string(),
+            Duration::from_millis(20),
+        )
+    }
+
+    fn check(yaml: &str) -> Vec<(String, bool)> {
+        let assertions: Assertions = serde_yaml::from_str(yaml).unwrap();
+        assertions.validate().unwrap();
+        assertions
+            .check(&response())
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.name, r.passed))
+            .collect()
+    }
+
+    #[test]
+    fn status_and_time() {
+        let results = check("status: 201\nmax_time_ms: 100");
+        assert_eq!(
+            results,
+            [
+                ("status == 201".to_string(), false),
+                ("time <= 100ms".to_string(), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn headers_match_any_value() {
+        let results = check(
+            r#"
+headers:
+  - name: content-type
+    regex: json
+  - name: set-cookie
+    eq: b=2
+  - name: etag
+    exists: false
+"#,
+        );
+        assert!(results.iter().all(|(_, passed)| *passed), "{:?}", results);
+    }
+
+    #[test]
+    fn json_paths() {
+        let results = check(
+            r#"
+json:
+  - path: $.id
+    eq: 7
+  - path: name
+    regex: ^to
+  - path: tags
+    type: array
+  - path: missing
+"#,
+        );
+        let passed = results.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+        assert_eq!(passed, [true, true, true, false]);
+    }
+
+    #[test]
+    fn failures_explain_the_actual_value() {
+        let assertions: Assertions = serde_yaml::from_str("json: [{path: id, eq: 8}]").unwrap();
+        let result = &assertions.check(&response()).unwrap()[0];
+        assert_eq!(result.to_string(), "FAIL id == 8 (got 7)");
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected() {
+        let assertions: Assertions =
+            serde_yaml::from_str("headers: [{name: a, regex: '('}]").unwrap();
+        assert!(assertions.validate().is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::fs;
+
+use crate::{template::Vars, ValidateConfig};
+
+/// Rows of variables a profile is run with, one case per row. Rows come from `values`, then
+/// from `file`, a CSV file with a header line or a JSONL file of objects.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq)]
+pub struct Matrix {
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub values: Vec<Vars>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub file: Option<String>,
+}
+
+impl ValidateConfig for Matrix {
+    fn validate(&self) -> Result<()> {
+        if self.values.is_empty() && self.file.is_none() {
+            return Err(anyhow!("matrix needs values or a file"));
+        }
+        if let Some(file) = &self.file {
+            file_format(file)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FileFormat {
+    Csv,
+    Jsonl,
+}
+
+impl Matrix {
+    pub async fn rows(&self) -> Result<Vec<Vars>> {
+        let mut rows = self.values.clone();
+
+        if let Some(file) = &self.file {
+            let content = fs::read_to_string(file)
+                .await
+                .context(format!("failed to read {}", file))?;
+            let parsed = match file_format(file)? {
+                FileFormat::Csv => parse_csv(&content),
+                FileFormat::Jsonl => parse_jsonl(&content),
+            };
+            rows.extend(parsed.context(format!("failed to parse {}", file))?);
+        }
+
+        Ok(rows)
+    }
+}
+
+/// A readable name for the case of `row`, e.g. `id=1, lang=en`.
+pub fn case_name(row: &Vars) -> String {
+    let mut pairs = row
+        .iter()
+        .map(|(k, v)| match v {
+            Value::String(s) => format!("{}={}", k, s),
+            v => format!("{}={}", k, v),
+        })
+        .collect::<Vec<_>>();
+    pairs.sort();
+    pairs.join(", ")
+}
+
+/// A case name made safe for a file name, e.g. `id=1,lang=en`.
+pub fn case_slug(name: &str) -> String {
+    name.replace(", ", ",")
+        .chars()
+        .map(|c| match c {
+            c if c.is_alphanumeric() || "=,._-".contains(c) => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn file_format(file: &str) -> Result<FileFormat> {
+    match Path::new(file).extension().and_then(|v| v.to_str()) {
+        Some("csv") => Ok(FileFormat::Csv),
+        Some("jsonl") | Some("ndjson") => Ok(FileFormat::Jsonl),
+        _ => Err(anyhow!("matrix file must be .csv or .jsonl: {}", file)),
+    }
+}
+
+fn parse_jsonl(content: &str) -> Result<Vec<Vars>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).map_err(|e| anyhow!("line {}: {}", i + 1, e)))
+        .collect()
+}
+
+/// Fields that are valid json scalars, like numbers and booleans, keep their type.
+fn parse_csv(content: &str) -> Result<Vec<Vars>> {
+    let mut records = csv_records(content)?.into_iter();
+    let header = records.next().unwrap_or_default();
+
+    records
+        .enumerate()
+        .map(|(i, record)| {
+            if record.len() != header.len() {
+                // blank lines and quoted line breaks make line numbers unreliable
+                return Err(anyhow!(
+                    "row {}: expected {} fields, got {}",
+                    i + 1,
+                    header.len(),
+                    record.len()
+                ));
+            }
+            Ok(header
+                .iter()
+                .cloned()
+                .zip(
+                    record
+                        .into_iter()
+                        .map(|field| match serde_json::from_str::<Value>(&field) {
+                            Ok(v) if !v.is_object() && !v.is_array() && !v.is_string() => v,
+                            _ => Value::String(field),
+                        }),
+                )
+                .collect())
+        })
+        .collect()
+}
+
+fn csv_records(content: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = content.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.is_empty() => quoted = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                if record.iter().any(|f| !f.is_empty()) {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(anyhow!("unterminated quoted field"));
+    }
+    record.push(field);
+    if record.iter().any(|f| !f.is_empty()) {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars(value: Value) -> Vars {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn csv_fields_keep_scalar_types_and_quotes() {
+        let rows =
+            parse_csv("id,name,ok\r\n1,\"Doe, \"\"J\"\"\",true\n\n2,x\ny,false\n").unwrap_err();
+        assert_eq!(rows.to_string(), "row 2: expected 3 fields, got 2");
+
+        let rows = parse_csv("id,name,ok\r\n1,\"Doe, \"\"J\"\"\",true\n\n2,007,\"a\nb\"").unwrap();
+        assert_eq!(
+            rows,
+            [
+                vars(json!({"id": 1, "name": "Doe, \"J\"", "ok": true})),
+                vars(json!({"id": 2, "name": "007", "ok": "a\nb"})),
+            ]
+        );
+        assert!(parse_csv("a\n\"open").is_err());
+    }
+
+    #[tokio::test]
+    async fn rows_come_from_values_then_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("rows.jsonl");
+        std::fs::write(&file, "{\"id\": 2}\n\n{\"id\": 3}\n").unwrap();
+        let matrix = Matrix {
+            values: vec![vars(json!({"id": 1}))],
+            file: Some(file.to_str().unwrap().to_string()),
+        };
+
+        assert!(matrix.validate().is_ok());
+        let ids = matrix.rows().await.unwrap();
+        assert_eq!(
+            ids.iter().map(|r| r["id"].clone()).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+
+        assert!(Matrix::default().validate().is_err());
+        let txt = Matrix {
+            file: Some("rows.txt".to_string()),
+            ..Default::default()
+        };
+        assert!(txt.validate().is_err());
+        assert!(parse_jsonl("{\"id\": 1}\n[1]").is_err());
+    }
+
+    #[test]
+    fn case_names_are_sorted_and_slugs_are_file_safe() {
+        let name = case_name(&vars(json!({"lang": "en/us", "id": 1})));
+        assert_eq!(name, "id=1, lang=en/us");
+        assert_eq!(case_slug(&name), "id=1,lang=en_us");
+    }
+}
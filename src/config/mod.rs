@@ -1,25 +1,43 @@
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+pub mod assertion;
+pub mod matrix;
+pub mod workflow;
 pub mod xdiff;
 pub mod xreq;
 
+pub use assertion::*;
+pub use matrix::*;
+pub use workflow::*;
 pub use xdiff::*;
 pub use xreq::*;
 
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 /// Represents a request profile.
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
-    Client, Method, Response,
+    Client, Method, Response, StatusCode, Version,
 };
 use serde_json::json;
 use tokio::fs;
 use url::Url;
 
-use crate::ExtraArgs;
+use crate::{
+    cassette::{CassetteMode, RecordedRequest},
+    cookie::CookieJar,
+    json_path,
+    schema::{SchemaViolation, Validator},
+    stats::Stats,
+    template::{render_str, render_value, Vars},
+    ExtraArgs,
+};
 
 #[allow(async_fn_in_trait)]
 pub trait LoadYaml: Sized + ValidateConfig + DeserializeOwned {
@@ -58,6 +76,9 @@ pub struct RequestProfile {
         default
     )]
     pub headers: HeaderMap,
+    /// File used to persist cookies between requests and runs.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub cookie_jar: Option<String>,
 }
 
 impl ValidateConfig for RequestProfile {
@@ -98,13 +119,31 @@ impl RequestProfile {
             params,
             body,
             headers,
+            cookie_jar: None,
         }
     }
 
+    /// Send the request. Cookies of a `cookie_jar` are kept in memory for the rest of the run,
+    /// see [`crate::cookie::save_all`].
     pub async fn send(&self, extra: &ExtraArgs) -> Result<ResponseExt> {
-        let (headers, body, query) = self.generate(extra)?;
+        self.send_with(&self.client().await?, extra).await
+    }
+
+    /// A client for this request, using its `cookie_jar` if any. Build it once to send the
+    /// request many times.
+    pub async fn client(&self) -> Result<Client> {
+        Ok(match &self.cookie_jar {
+            Some(path) => {
+                let jar = CookieJar::shared(path).await?;
+                Client::builder().cookie_provider(jar.provider()).build()?
+            }
+            None => Client::new(),
+        })
+    }
 
-        let client = Client::new();
+    /// Send the request with `client`, see [`Self::client`].
+    pub async fn send_with(&self, client: &Client, extra: &ExtraArgs) -> Result<ResponseExt> {
+        let (headers, body, query) = self.generate(extra)?;
 
         let request = client
             .request(self.method.clone(), self.url.clone())
@@ -113,9 +152,61 @@ impl RequestProfile {
             .query(&query)
             .build()?;
 
+        let cassette = extra.cassette.as_ref();
+        let recorded = cassette.map(|_| RecordedRequest::from_request(&request));
+        if let (Some(cassette), Some(recorded)) = (cassette, &recorded) {
+            if cassette.mode() == CassetteMode::Replay {
+                return cassette.replay(recorded);
+            }
+        }
+
+        let start = Instant::now();
         let res = client.execute(request).await?;
+        let res = ResponseExt::from_response(res, start).await?;
+
+        if let (Some(cassette), Some(recorded)) = (cassette, recorded) {
+            cassette.record(recorded, &res).await?;
+        }
+
+        Ok(res)
+    }
+
+    /// Resolve `{{ name }}` placeholders in the url, params, body and headers.
+    pub fn render(&self, vars: &Vars) -> Result<Self> {
+        // placeholders in the url path are percent encoded by the parser
+        static PLACEHOLDER: OnceLock<Regex> = OnceLock::new();
+        let placeholder = PLACEHOLDER.get_or_init(|| Regex::new(r"%7B%7B(.*?)%7D%7D").unwrap());
+        let url = placeholder.replace_all(self.url.as_str(), |caps: &regex::Captures| {
+            format!("{{{{{}}}}}", caps[1].replace("%20", " "))
+        });
+
+        let mut headers = HeaderMap::new();
+        for (key, value) in &self.headers {
+            // values without placeholders are kept byte for byte, even when not valid UTF-8
+            let text = String::from_utf8_lossy(value.as_bytes());
+            let value = match text.contains("{{") {
+                true => HeaderValue::from_str(&render_str(&text, vars)?)?,
+                false => value.clone(),
+            };
+            headers.append(key.clone(), value);
+        }
 
-        Ok(ResponseExt(res))
+        Ok(Self {
+            url: Url::parse(&render_str(&url, vars)?)?,
+            params: self
+                .params
+                .as_ref()
+                .map(|v| render_value(v, vars))
+                .transpose()?,
+            body: self
+                .body
+                .as_ref()
+                .map(|v| render_value(v, vars))
+                .transpose()?,
+            headers,
+            cookie_jar: self.cookie_jar.clone(),
+            method: self.method.clone(),
+        })
     }
 
     pub fn generate(&self, extra: &ExtraArgs) -> Result<(HeaderMap, String, serde_json::Value)> {
@@ -142,9 +233,15 @@ impl RequestProfile {
             query[key] = json!(value);
         }
 
-        let content_type = headers.get("content-type").unwrap();
+        // parameters such as `charset` do not change how the body is encoded
+        let content_type = headers
+            .get("content-type")
+            .map(|v| v.to_str())
+            .transpose()?
+            .unwrap_or_default();
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
 
-        match content_type.to_str()? {
+        match media_type.to_ascii_lowercase().as_str() {
             "application/json" => {
                 body = serde_json::to_value(&body)?;
                 Ok((headers, body.to_string(), query))
@@ -154,27 +251,28 @@ impl RequestProfile {
                 Ok((headers, body, query))
             }
 
-            _ => panic!("Unsupported content type: {:?}", content_type),
+            _ => Err(anyhow!("Unsupported content type: {}", content_type)),
         }
     }
 }
 
 fn empty_json_value(v: &Option<serde_json::Value>) -> bool {
     v.as_ref()
-        .map_or(true, |v| v.is_null() || v.as_object().unwrap().is_empty())
+        .is_none_or(|v| v.is_null() || v.as_object().unwrap().is_empty())
 }
 
 impl FromStr for RequestProfile {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let url = Url::parse(s)?;
-        let qs = url.query_pairs().collect::<Vec<_>>();
+        let mut url = Url::parse(s)?;
 
+        // the query moves into params, which `send` appends to the url
         let mut params = json!({});
-        for (key, value) in qs {
+        for (key, value) in url.query_pairs() {
             params[&*key] = json!(value);
         }
+        url.set_query(None);
 
         Ok(RequestProfile::new(
             url,
@@ -186,38 +284,106 @@ impl FromStr for RequestProfile {
     }
 }
 
+/// A response whose body has been read, so it can be inspected more than once.
 #[derive(Debug)]
-pub struct ResponseExt(Response);
+pub struct ResponseExt {
+    version: Version,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: String,
+    ttfb: Duration,
+    elapsed: Duration,
+}
 
 impl ResponseExt {
-    pub async fn get_text(self, skip_headers: &[String], skip_body: &[String]) -> Result<String> {
-        let header_text = self.get_header_text(skip_headers)?;
-        let body_text = self.get_body_text(skip_body).await?;
+    pub fn new(
+        version: Version,
+        status: StatusCode,
+        headers: HeaderMap,
+        body: String,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            version,
+            status,
+            headers,
+            body,
+            ttfb: elapsed,
+            elapsed,
+        }
+    }
+
+    pub fn with_ttfb(self, ttfb: Duration) -> Self {
+        Self { ttfb, ..self }
+    }
+
+    /// Read the whole body of `res`. `start` is when the request was sent.
+    pub async fn from_response(res: Response, start: Instant) -> Result<Self> {
+        let ttfb = start.elapsed();
+        let version = res.version();
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.text().await?;
+
+        Ok(Self {
+            version,
+            status,
+            headers,
+            body,
+            ttfb,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Time from sending the request until the response headers arrived.
+    pub fn ttfb(&self) -> Duration {
+        self.ttfb
+    }
+
+    /// Time from sending the request until the body was fully read.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn get_text(&self, response: &ResponseProfile) -> Result<String> {
+        let header_text = self.get_header_text(response)?;
+        let body_text = self.get_body_text(&response.skip_body)?;
 
         Ok(format!("{}{}", header_text, body_text))
     }
 
-    pub async fn get_body_text(self, skip_body: &[String]) -> Result<String> {
+    pub fn get_body_text(&self, skip_body: &[String]) -> Result<String> {
         let mut output = String::new();
         let is_json_content_type = self
-            .0
-            .headers()
-            .get("content-type")
-            .map_or(false, |v| v.to_str().unwrap().contains("application/json"));
+            .header_values("content-type")
+            .iter()
+            .any(|v| v.contains("application/json"));
 
         if !is_json_content_type {
-            let text = self.0.text().await?;
-            writeln!(&mut output, "{}", text)?;
+            writeln!(&mut output, "{}", self.body)?;
 
             Ok(output)
         } else {
-            let text = self.0.text().await?;
-            let mut body = serde_json::from_str::<serde_json::Value>(&text)?;
+            let mut body = serde_json::from_str::<serde_json::Value>(&self.body)?;
 
-            if let Some(mut_body) = body.as_object_mut() {
-                for key in skip_body {
-                    mut_body.remove(key);
-                }
+            for path in skip_body {
+                json_path::remove(&mut body, path)?;
             }
 
             writeln!(&mut output, "{}", &serde_json::to_string_pretty(&body)?)?;
@@ -226,15 +392,18 @@ impl ResponseExt {
         }
     }
 
-    pub fn get_header_text(&self, skip_headers: &[String]) -> Result<String> {
+    /// The status line and the headers `response` compares, sorted by name with one line per
+    /// value.
+    pub fn get_header_text(&self, response: &ResponseProfile) -> Result<String> {
         let mut output = String::new();
-        let headers = self.0.headers().clone();
 
-        writeln!(&mut output, "{:?} {}", self.0.version(), self.0.status())?;
+        writeln!(&mut output, "{:?} {}", self.version, self.status)?;
 
-        for key in headers.keys() {
-            if !skip_headers.contains(&key.to_string()) {
-                writeln!(&mut output, "{}: {}", key, headers[key].to_str()?)?;
+        for key in self.get_headers() {
+            if response.compares_header(&key)? {
+                for value in self.header_values(&key) {
+                    writeln!(&mut output, "{}: {}", key, value)?;
+                }
             }
         }
 
@@ -243,33 +412,410 @@ impl ResponseExt {
         Ok(output)
     }
 
+    /// The header names, sorted.
     pub fn get_headers(&self) -> Vec<String> {
-        self.0
-            .headers()
+        let mut names = self
+            .headers
             .keys()
             .map(|v| v.to_string())
-            .collect::<Vec<_>>()
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+
+    /// Every value of the header called `name`, sorted, so repeated headers compare the same
+    /// whatever their order.
+    pub fn header_values(&self, name: &str) -> Vec<String> {
+        let mut values = self.header_values_in_order(name);
+        values.sort();
+        values
+    }
+
+    /// Every value of the header called `name`, in the order they were received. Bytes that
+    /// are not valid UTF-8 are replaced rather than failing.
+    pub fn header_values_in_order(&self, name: &str) -> Vec<String> {
+        self.headers
+            .get_all(name)
+            .iter()
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).to_string())
+            .collect()
     }
 }
 
 /// Represents a response profile.
 #[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
 pub struct ResponseProfile {
+    /// Headers to leave out. Names match case-insensitively and may be globs like `x-amz-*`,
+    /// or regexes between slashes like `/^x-(request|trace)-id$/`.
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub skip_headers: Vec<String>,
+    /// When set, only the headers matching one of these are compared. Same syntax as
+    /// `skip_headers`, which still applies.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub only_headers: Vec<String>,
+    /// JSON paths of body values to leave out, e.g. `updated_at` or `items[*].id`.
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub skip_body: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub schema: Option<ResponseSchema>,
+    /// How much worse the requests of a diff may perform than the baseline.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub thresholds: Thresholds,
+}
+
+/// JSON Schema the response body must match, inline or taken from an OpenAPI document.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ResponseSchema {
+    OpenApi {
+        /// path of the OpenAPI document
+        openapi: String,
+        /// e.g. `#/components/schemas/Todo`
+        #[serde(rename = "ref")]
+        reference: String,
+    },
+    Inline(serde_json::Value),
+}
+
+impl ValidateConfig for ResponseProfile {
+    fn validate(&self) -> Result<()> {
+        for pattern in self.skip_headers.iter().chain(&self.only_headers) {
+            header_pattern(pattern)?;
+        }
+        for path in &self.skip_body {
+            json_path::parse(path)?;
+        }
+
+        Ok(())
+    }
 }
 
 impl ResponseProfile {
     pub fn new(skip_headers: Vec<String>, skip_body: Vec<String>) -> Self {
         Self {
             skip_headers,
+            only_headers: vec![],
             skip_body,
+            schema: None,
+            thresholds: Thresholds::default(),
+        }
+    }
+
+    /// Whether the header called `name` is compared, given `only_headers` and `skip_headers`.
+    /// Fails on an invalid pattern, even when the profile was never validated.
+    pub fn compares_header(&self, name: &str) -> Result<bool> {
+        let matches = |patterns: &[String]| -> Result<bool> {
+            for pattern in patterns {
+                if header_pattern(pattern)?.is_match(name) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        };
+
+        Ok(
+            (self.only_headers.is_empty() || matches(&self.only_headers)?)
+                && !matches(&self.skip_headers)?,
+        )
+    }
+
+    /// Check the response body against `schema`, if there is one.
+    pub async fn validate_schema(&self, res: &ResponseExt) -> Result<Vec<SchemaViolation>> {
+        let Some(schema) = &self.schema else {
+            return Ok(vec![]);
+        };
+        let body: serde_json::Value = match serde_json::from_str(res.body()) {
+            Ok(body) => body,
+            Err(e) => {
+                return Ok(vec![SchemaViolation {
+                    path: "$".to_string(),
+                    message: format!("response body is not valid json: {}", e),
+                }])
+            }
+        };
+
+        match schema {
+            ResponseSchema::Inline(schema) => Validator::new(schema).validate(schema, &body),
+            ResponseSchema::OpenApi { openapi, reference } => {
+                let doc: serde_yaml::Value = serde_yaml::from_str(
+                    &fs::read_to_string(openapi)
+                        .await
+                        .context(format!("failed to read {}", openapi))?,
+                )?;
+                let doc = serde_json::to_value(doc)?;
+                let validator = Validator::new(&doc);
+                validator.validate(validator.resolve(reference)?, &body)
+            }
+        }
+    }
+}
+
+/// Limits on the medians of a request compared to the baseline. Percentages are relative to
+/// the baseline.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct Thresholds {
+    /// slowdown of the total response time, in percent
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub latency_pct: Option<u64>,
+    /// slowdown of the total response time, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub latency_ms: Option<u64>,
+    /// growth of the body size, in percent
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub size_pct: Option<u64>,
+}
+
+impl Thresholds {
+    /// Describe every limit `candidate` exceeds compared to `base`.
+    pub fn check(&self, base: &Stats, candidate: &Stats) -> Vec<String> {
+        let mut regressions = vec![];
+        let base_ms = base.total.median.as_millis() as u64;
+        let candidate_ms = candidate.total.median.as_millis() as u64;
+        let slowdown = candidate_ms.saturating_sub(base_ms);
+
+        if let Some(limit) = self.latency_ms {
+            if slowdown > limit {
+                regressions.push(format!(
+                    "total time {}ms vs {}ms, {}ms slower (limit {}ms)",
+                    candidate_ms, base_ms, slowdown, limit
+                ));
+            }
         }
+        if let Some(limit) = self.latency_pct {
+            if let Some(pct) = increase_pct(base_ms, candidate_ms).filter(|&p| p > limit) {
+                regressions.push(format!(
+                    "total time {}ms vs {}ms, {}% slower (limit {}%)",
+                    candidate_ms, base_ms, pct, limit
+                ));
+            }
+        }
+        if let Some(limit) = self.size_pct {
+            let (base_size, size) = (base.body_size.median, candidate.body_size.median);
+            if let Some(pct) = increase_pct(base_size as u64, size as u64).filter(|&p| p > limit) {
+                regressions.push(format!(
+                    "body size {} vs {} bytes, {}% larger (limit {}%)",
+                    size, base_size, pct, limit
+                ));
+            }
+        }
+
+        regressions
+    }
+}
+
+/// A case-insensitive regex for a header pattern: a regex between slashes, otherwise a glob
+/// where `*` and `?` are wildcards. Each pattern is compiled once.
+fn header_pattern(pattern: &str) -> Result<Regex> {
+    static COMPILED: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+    let compiled = COMPILED.get_or_init(Default::default);
+    if let Some(regex) = compiled.lock().unwrap().get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        Some(regex) => format!("(?i){}", regex),
+        None => format!(
+            "(?i)^{}$",
+            regex::escape(pattern)
+                .replace(r"\*", ".*")
+                .replace(r"\?", ".")
+        ),
+    };
+
+    let regex = Regex::new(&regex).context(format!("invalid header pattern: {}", pattern))?;
+    compiled
+        .lock()
+        .unwrap()
+        .insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+fn increase_pct(base: u64, value: u64) -> Option<u64> {
+    match base {
+        0 => None,
+        _ => Some(value.saturating_sub(base) * 100 / base),
     }
 }
 
 pub fn is_default<T: Default + PartialEq>(value: &T) -> bool {
     value == &T::default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{diff::ResponseDiff, stats::Sample};
+
+    fn stats(total_ms: u64, body_size: usize) -> Stats {
+        Stats::new(&[Sample {
+            ttfb: Duration::from_millis(total_ms),
+            total: Duration::from_millis(total_ms),
+            body_size,
+            header_count: 1,
+        }])
+    }
+
+    #[test]
+    fn thresholds_flag_only_the_limits_exceeded() {
+        let thresholds = Thresholds {
+            latency_pct: Some(50),
+            latency_ms: Some(100),
+            size_pct: Some(10),
+        };
+
+        assert!(thresholds
+            .check(&stats(100, 100), &stats(140, 110))
+            .is_empty());
+        assert_eq!(
+            thresholds.check(&stats(100, 100), &stats(300, 120)),
+            [
+                "total time 300ms vs 100ms, 200ms slower (limit 100ms)",
+                "total time 300ms vs 100ms, 200% slower (limit 50%)",
+                "body size 120 vs 100 bytes, 20% larger (limit 10%)",
+            ]
+        );
+        // faster or empty baselines are never a regression
+        assert!(thresholds.check(&stats(300, 0), &stats(100, 50)).is_empty());
+        assert!(Thresholds::default()
+            .check(&stats(1, 1), &stats(1000, 1000))
+            .is_empty());
+    }
+
+    #[test]
+    fn header_patterns_match_globs_and_regexes_case_insensitively() {
+        let mut response = ResponseProfile::new(
+            vec![
+                "X-Amz-*".to_string(),
+                "/^x-(request|trace)-id$/".to_string(),
+            ],
+            vec![],
+        );
+        for (name, compared) in [
+            ("content-type", true),
+            ("x-amz-date", false),
+            ("x-request-id", false),
+            ("x-request-ids", true),
+        ] {
+            assert_eq!(
+                response.compares_header(name).unwrap(),
+                compared,
+                "{}",
+                name
+            );
+        }
+
+        response.only_headers = vec!["content-?ype".to_string(), "x-*".to_string()];
+        assert!(response.compares_header("Content-Type").unwrap());
+        assert!(!response.compares_header("etag").unwrap());
+        assert!(!response.compares_header("x-amz-id").unwrap());
+    }
+
+    #[test]
+    fn invalid_header_patterns_fail_without_validate() {
+        let response = ResponseProfile::new(vec!["/(unclosed/".to_string()], vec![]);
+        assert!(response.validate().is_err());
+
+        let e = response.compares_header("etag").unwrap_err();
+        assert_eq!(e.to_string(), "invalid header pattern: /(unclosed/");
+    }
+
+    fn response_with(headers: &[(&'static str, &[u8])]) -> ResponseExt {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_bytes(value).unwrap());
+        }
+        ResponseExt::new(
+            Version::HTTP_11,
+            StatusCode::OK,
+            map,
+            "ok".to_string(),
+            Duration::ZERO,
+        )
+    }
+
+    #[test]
+    fn repeated_and_non_utf8_headers_keep_every_value() {
+        let res = response_with(&[
+            ("vary", b"origin"),
+            ("set-cookie", b"b=2"),
+            ("vary", b"accept"),
+            ("set-cookie", b"a=1"),
+            ("x-name", b"caf\xe9"),
+        ]);
+
+        assert_eq!(res.header_values_in_order("set-cookie"), ["b=2", "a=1"]);
+        assert_eq!(res.header_values("set-cookie"), ["a=1", "b=2"]);
+        assert_eq!(res.header_values("x-name"), ["caf\u{fffd}"]);
+        assert_eq!(
+            res.get_header_text(&ResponseProfile::default()).unwrap(),
+            "HTTP/1.1 200 OK\nset-cookie: a=1\nset-cookie: b=2\nvary: accept\nvary: origin\nx-name: caf\u{fffd}\n\n"
+        );
+
+        // the order of repeated values does not matter, their number does
+        let reordered = response_with(&[
+            ("set-cookie", b"a=1"),
+            ("set-cookie", b"b=2"),
+            ("vary", b"accept"),
+            ("vary", b"origin"),
+            ("x-name", b"caf\xe9"),
+        ]);
+        let profile = ResponseProfile::default();
+        assert!(ResponseDiff::new(&res, &reordered, &profile)
+            .unwrap()
+            .is_empty());
+
+        let fewer = response_with(&[
+            ("set-cookie", b"a=1"),
+            ("vary", b"accept"),
+            ("vary", b"origin"),
+            ("x-name", b"caf\xe9"),
+        ]);
+        let diff = ResponseDiff::new(&res, &fewer, &profile).unwrap();
+        assert_eq!(diff.headers.len(), 1);
+        assert_eq!(diff.headers[0].old, ["a=1", "b=2"]);
+        assert_eq!(diff.headers[0].new, ["a=1"]);
+    }
+
+    #[test]
+    fn generate_encodes_by_media_type() {
+        let mut request: RequestProfile = "http://localhost/".parse().unwrap();
+        request.body = Some(json!({"a": 1}));
+        request.headers.insert(
+            "content-type",
+            HeaderValue::from_static("Application/JSON; charset=utf-8"),
+        );
+        let (_, body, _) = request.generate(&ExtraArgs::default()).unwrap();
+        assert_eq!(body, r#"{"a":1}"#);
+
+        request.headers.insert(
+            "content-type",
+            HeaderValue::from_static("application/x-www-form-urlencoded; charset=utf-8"),
+        );
+        let (_, body, _) = request.generate(&ExtraArgs::default()).unwrap();
+        assert_eq!(body, "a=1");
+
+        request
+            .headers
+            .insert("content-type", HeaderValue::from_static("text/plain"));
+        let e = request.generate(&ExtraArgs::default()).unwrap_err();
+        assert_eq!(e.to_string(), "Unsupported content type: text/plain");
+    }
+
+    #[test]
+    fn render_fills_headers_and_keeps_other_bytes() {
+        let mut request: RequestProfile = "http://localhost/todos/{{ id }}".parse().unwrap();
+        request.headers.insert(
+            "authorization",
+            HeaderValue::from_static("Bearer {{token}}"),
+        );
+        request
+            .headers
+            .insert("x-name", HeaderValue::from_bytes(b"caf\xe9").unwrap());
+        let vars: Vars = serde_json::from_value(json!({"id": 1, "token": "t"})).unwrap();
+
+        let rendered = request.render(&vars).unwrap();
+        assert_eq!(rendered.url.as_str(), "http://localhost/todos/1");
+        assert_eq!(rendered.headers["authorization"], "Bearer t");
+        assert_eq!(rendered.headers["x-name"].as_bytes(), b"caf\xe9");
+    }
+}
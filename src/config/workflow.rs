@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{json_path, template::Vars, ExtraArgs, RequestProfile, ValidateConfig};
+
+/// A request that runs before the main request of a profile and stores values from its
+/// response into variables.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WorkflowStep {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub name: Option<String>,
+    pub request: RequestProfile,
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub extract: HashMap<String, Extract>,
+}
+
+/// Where to take a variable from. `json` and `header` select the source (the raw body is used
+/// when neither is set), `regex` then narrows it down to its first capture group.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct Extract {
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub json: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub header: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub regex: Option<String>,
+}
+
+impl ValidateConfig for WorkflowStep {
+    fn validate(&self) -> Result<()> {
+        self.request.validate()?;
+
+        for (name, extract) in &self.extract {
+            extract
+                .validate()
+                .context(format!("invalid extraction for variable: {}", name))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ValidateConfig for Extract {
+    fn validate(&self) -> Result<()> {
+        if self.json.is_some() && self.header.is_some() {
+            anyhow::bail!("json and header can not be used together");
+        }
+
+        if let Some(path) = &self.json {
+            json_path::parse(path)?;
+        }
+
+        if let Some(re) = &self.regex {
+            Regex::new(re)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl WorkflowStep {
+    /// Send the request rendered with `vars`, then add the extracted values to `vars`.
+    pub async fn run(&self, vars: &mut Vars, extra: &ExtraArgs) -> Result<()> {
+        let res = self.request.render(vars)?.send(extra).await?;
+        let text = res.body();
+
+        for (name, extract) in &self.extract {
+            // a header may be repeated (e.g. set-cookie), so every value is a candidate
+            let candidates = match (&extract.json, &extract.header) {
+                (Some(path), _) => {
+                    let body: Value = serde_json::from_str(text)?;
+                    let value = json_path::get(&body, path)?
+                        .cloned()
+                        .ok_or_else(|| anyhow!("{} not found in response body", path))?;
+                    vec![value]
+                }
+                (_, Some(header)) => {
+                    let values = res
+                        .header_values_in_order(header)
+                        .into_iter()
+                        .map(Value::String)
+                        .collect::<Vec<_>>();
+                    if values.is_empty() {
+                        anyhow::bail!("header {} not found in response", header);
+                    }
+                    values
+                }
+                _ => vec![Value::String(text.to_string())],
+            };
+
+            let value = match &extract.regex {
+                Some(re) => {
+                    let re = Regex::new(re)?;
+                    candidates
+                        .iter()
+                        .find_map(|value| {
+                            let haystack = match value {
+                                Value::String(v) => v.clone(),
+                                v => v.to_string(),
+                            };
+                            let caps = re.captures(&haystack)?;
+                            let m = caps.get(1).or_else(|| caps.get(0))?;
+                            Some(Value::String(m.as_str().to_string()))
+                        })
+                        .ok_or_else(|| anyhow!("regex {} did not match", re))?
+                }
+                None => candidates.into_iter().next().unwrap(),
+            };
+
+            vars.insert(name.clone(), value);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use hyper::{Body, Response};
+    use serde_json::json;
+
+    fn extract(json: Option<&str>, header: Option<&str>, regex: Option<&str>) -> Extract {
+        Extract {
+            json: json.map(String::from),
+            header: header.map(String::from),
+            regex: regex.map(String::from),
+        }
+    }
+
+    #[tokio::test]
+    async fn run_extracts_from_body_and_headers() {
+        let addr = serve(|parts, _| {
+            Response::builder()
+                .header("content-type", "application/json")
+                .header("set-cookie", "theme=dark")
+                .header("set-cookie", "session=s1; Path=/")
+                .body(Body::from(format!(
+                    r#"{{"token": "t-{}", "user": {{"id": 7}}}}"#,
+                    parts.uri.path().trim_start_matches('/')
+                )))
+                .unwrap()
+        });
+        let step = WorkflowStep {
+            name: None,
+            request: format!("http://{}/{{{{ tenant }}}}", addr).parse().unwrap(),
+            extract: HashMap::from([
+                ("token".to_string(), extract(Some("$.token"), None, None)),
+                ("user".to_string(), extract(Some("user.id"), None, None)),
+                (
+                    "session".to_string(),
+                    extract(None, Some("set-cookie"), Some("session=(\\w+)")),
+                ),
+            ]),
+        };
+
+        let mut vars = Vars::from([("tenant".to_string(), json!("acme"))]);
+        step.run(&mut vars, &ExtraArgs::default()).await.unwrap();
+
+        assert_eq!(vars["token"], json!("t-acme"));
+        assert_eq!(vars["user"], json!(7));
+        assert_eq!(vars["session"], json!("s1"));
+    }
+
+    #[test]
+    fn extract_rejects_json_and_header_together() {
+        assert!(extract(Some("a"), Some("b"), None).validate().is_err());
+        assert!(extract(None, None, Some("(")).validate().is_err());
+        assert!(extract(Some("a.b"), None, Some("(\\d+)"))
+            .validate()
+            .is_ok());
+    }
+}
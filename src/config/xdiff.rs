@@ -1,17 +1,26 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use futures::future::try_join_all;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
 
 use crate::{
-    is_default, utils::text_diff, ExtraArgs, LoadYaml, RequestProfile, ResponseProfile,
-    ValidateConfig,
+    config::matrix::{case_name, Matrix},
+    diff::{ResponseDiff, ResponseSections},
+    is_default, json_path,
+    schema::SchemaViolation,
+    snapshot::{check_snapshot, SnapshotStatus},
+    stats::{Sample, Stats},
+    utils::{compare_report, regression_report, response_diff_report, schema_report, stats_report},
+    ExtraArgs, LoadYaml, RequestProfile, ResponseExt, ResponseProfile, ValidateConfig,
 };
 
 /// Represents the configuration for performing diffs.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct DiffConfig {
     #[serde(flatten)]
-    pub profiles: HashMap<String, DiffProfile>,
+    pub profiles: IndexMap<String, DiffProfile>,
 }
 impl LoadYaml for DiffConfig {}
 
@@ -28,7 +37,7 @@ impl ValidateConfig for DiffConfig {
 }
 
 impl DiffConfig {
-    pub fn new(profiles: HashMap<String, DiffProfile>) -> Self {
+    pub fn new(profiles: IndexMap<String, DiffProfile>) -> Self {
         Self { profiles }
     }
 
@@ -37,28 +46,110 @@ impl DiffConfig {
     }
 }
 
+/// Outcome of diffing a profile.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffReport {
+    #[serde(skip)]
+    pub output: String,
+    /// whether any response differs from the baseline
+    pub changed: bool,
+    /// the request the others are compared with
+    pub baseline: String,
+    /// how each of the other responses differs from the baseline
+    pub diffs: IndexMap<String, ResponseDiff>,
+    /// thresholds exceeded, prefixed with the request
+    pub regressions: Vec<String>,
+    /// schema violations of each response, when the profile has a schema
+    pub violations: IndexMap<String, Vec<SchemaViolation>>,
+    /// timing and size of each response
+    pub stats: IndexMap<String, Stats>,
+}
+
 /// Represents a diff profile.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct DiffProfile {
-    pub request1: RequestProfile,
-    pub request2: RequestProfile,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub request1: Option<RequestProfile>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub request2: Option<RequestProfile>,
+    /// Further requests by name, e.g. one per region.
+    #[serde(skip_serializing_if = "IndexMap::is_empty", default)]
+    pub requests: IndexMap<String, RequestProfile>,
+    /// The request the others are compared with, the first one by default. `majority` picks
+    /// the response most of the requests agree on.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub baseline: Option<String>,
+    /// Run the profile once per row, filling the row into the `{{ name }}` placeholders of
+    /// the requests.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub matrix: Option<Matrix>,
     #[serde(skip_serializing_if = "is_default", default)]
     pub response: ResponseProfile,
+    /// Send each request only after the previous one has finished, for APIs that must be
+    /// called in order.
+    #[serde(skip_serializing_if = "is_default", default)]
+    pub sequential: bool,
 }
 
+const MAJORITY: &str = "majority";
+
 impl ValidateConfig for DiffProfile {
     fn validate(&self) -> Result<()> {
-        self.request1
-            .validate()
-            .context("request1 failed to validate")?;
-        self.request2
+        let sides = self.sides();
+        if sides.len() < 2 {
+            return Err(anyhow!("at least two requests are needed to diff"));
+        }
+
+        for (i, (side, request)) in sides.iter().enumerate() {
+            if sides[..i].iter().any(|(other, _)| other == side) {
+                return Err(anyhow!("request {} is defined more than once", side));
+            }
+            if *side == MAJORITY {
+                return Err(anyhow!("{} is reserved for the baseline", MAJORITY));
+            }
+            request
+                .validate()
+                .context(format!("{} failed to validate", side))?;
+        }
+        if let Some(matrix) = &self.matrix {
+            matrix.validate().context("matrix failed to validate")?;
+        }
+        if let Some(baseline) = &self.baseline {
+            if baseline != MAJORITY && !sides.iter().any(|(side, _)| side == baseline) {
+                return Err(anyhow!(
+                    "baseline {} is not a request of the profile",
+                    baseline
+                ));
+            }
+        }
+        self.response
             .validate()
-            .context("request2 failed to validate")?;
+            .context("response failed to validate")?;
 
         Ok(())
     }
 }
 
+impl DiffReport {
+    /// Why the diff fails, if it does: the responses differ, a threshold is exceeded or a
+    /// response does not match the schema.
+    pub fn failure(&self) -> Option<String> {
+        let violations = self.violations.values().map(Vec::len).sum::<usize>();
+        if self.changed {
+            Some("responses differ".to_string())
+        } else if !self.regressions.is_empty() {
+            Some(format!(
+                "{} performance regressions",
+                self.regressions.len()
+            ))
+        } else if violations > 0 {
+            Some(format!("{} schema violations", violations))
+        } else {
+            None
+        }
+    }
+}
+
 impl DiffProfile {
     pub fn new(
         request1: RequestProfile,
@@ -66,22 +157,483 @@ impl DiffProfile {
         skip_headers: Vec<String>,
     ) -> Self {
         Self {
-            request1,
-            request2,
+            request1: Some(request1),
+            request2: Some(request2),
+            requests: IndexMap::new(),
+            baseline: None,
+            matrix: None,
             response: ResponseProfile::new(skip_headers, vec![]),
+            sequential: false,
+        }
+    }
+
+    /// Diff the response of every request with the baseline. With `repeat` above one the
+    /// requests are sent that many times to measure their timing, and the first responses are
+    /// diffed.
+    pub async fn diff(&self, args: &ExtraArgs, repeat: usize) -> Result<DiffReport> {
+        let sides = self.sides();
+        let responses = self.send_all(args).await?;
+        let mut samples = responses
+            .iter()
+            .map(|res| vec![Sample::from_response(res)])
+            .collect::<Vec<_>>();
+        for _ in 1..repeat {
+            for (samples, res) in samples.iter_mut().zip(self.send_all(args).await?) {
+                samples.push(Sample::from_response(&res));
+            }
+        }
+
+        let baseline = self.baseline_index(&responses)?;
+        let baseline_name = sides[baseline].0;
+
+        let mut output = String::new();
+        let mut diffs = IndexMap::new();
+        if sides.len() > 2 {
+            writeln!(&mut output, "baseline: {}", baseline_name)?;
+        }
+        for (i, (side, _)) in sides.iter().enumerate().filter(|(i, _)| *i != baseline) {
+            let diff = ResponseDiff::new(&responses[baseline], &responses[i], &self.response)?;
+            if sides.len() > 2 {
+                writeln!(
+                    &mut output,
+                    "{}",
+                    compare_report(side, baseline_name, diff.is_empty())
+                )?;
+            }
+            output.push_str(&response_diff_report(&diff)?);
+            diffs.insert(side.to_string(), diff);
+        }
+
+        let mut violations = IndexMap::new();
+        if self.response.schema.is_some() {
+            for ((side, _), res) in sides.iter().zip(&responses) {
+                let found = self.response.validate_schema(res).await?;
+                writeln!(&mut output, "{}", schema_report(side, &found))?;
+                violations.insert(side.to_string(), found);
+            }
+        }
+
+        let stats = samples.iter().map(|s| Stats::new(s)).collect::<Vec<_>>();
+        for ((side, _), stats) in sides.iter().zip(&stats) {
+            writeln!(&mut output, "{}", stats_report(side, stats))?;
+        }
+
+        let mut regressions = vec![];
+        for (i, (side, _)) in sides.iter().enumerate().filter(|(i, _)| *i != baseline) {
+            let found = self.response.thresholds.check(&stats[baseline], &stats[i]);
+            regressions.extend(found.into_iter().map(|r| format!("{}: {}", side, r)));
+        }
+        if !regressions.is_empty() {
+            writeln!(&mut output, "{}", regression_report(&regressions))?;
+        }
+
+        Ok(DiffReport {
+            output,
+            changed: diffs.values().any(|diff| !diff.is_empty()),
+            baseline: baseline_name.to_string(),
+            diffs,
+            regressions,
+            violations,
+            stats: sides
+                .iter()
+                .map(|(side, _)| side.to_string())
+                .zip(stats)
+                .collect(),
+        })
+    }
+
+    async fn send_all(&self, args: &ExtraArgs) -> Result<Vec<ResponseExt>> {
+        let sides = self.sides();
+        if self.sequential {
+            let mut responses = vec![];
+            for (_, request) in sides {
+                responses.push(request.send(args).await?);
+            }
+            Ok(responses)
+        } else {
+            try_join_all(sides.into_iter().map(|(_, request)| request.send(args))).await
+        }
+    }
+
+    /// Index of the baseline side. The majority is the response the most sides match, with
+    /// the settings of `response`, the earliest one on a tie.
+    fn baseline_index(&self, responses: &[ResponseExt]) -> Result<usize> {
+        match self.baseline.as_deref() {
+            Some(MAJORITY) => {
+                let sections = responses
+                    .iter()
+                    .map(|res| ResponseSections::new(res, &self.response))
+                    .collect::<Result<Vec<_>>>()?;
+                let matches = |i: usize| {
+                    sections
+                        .iter()
+                        .filter(|other| ResponseDiff::between(&sections[i], other).is_empty())
+                        .count()
+                };
+                Ok((0..sections.len())
+                    .rev()
+                    .max_by_key(|&i| matches(i))
+                    .unwrap_or_default())
+            }
+            Some(baseline) => Ok(self
+                .sides()
+                .iter()
+                .position(|(side, _)| side == &baseline)
+                .unwrap_or_default()),
+            None => Ok(0),
+        }
+    }
+
+    /// One profile per row of the matrix, named after the row.
+    pub async fn cases(&self) -> Result<Vec<(String, DiffProfile)>> {
+        let Some(matrix) = &self.matrix else {
+            return Ok(vec![]);
+        };
+
+        let mut cases = vec![];
+        for row in matrix.rows().await? {
+            let render = |request: &RequestProfile| request.render(&row);
+            let case = DiffProfile {
+                request1: self.request1.as_ref().map(render).transpose()?,
+                request2: self.request2.as_ref().map(render).transpose()?,
+                requests: self
+                    .requests
+                    .iter()
+                    .map(|(k, v)| Ok((k.clone(), render(v)?)))
+                    .collect::<Result<_>>()?,
+                matrix: None,
+                ..self.clone()
+            };
+            cases.push((case_name(&row), case));
+        }
+
+        Ok(cases)
+    }
+
+    /// The requests of this profile, named by their config key.
+    pub fn sides(&self) -> Vec<(&str, &RequestProfile)> {
+        let mut sides = vec![];
+        if let Some(request) = &self.request1 {
+            sides.push(("request1", request));
+        }
+        if let Some(request) = &self.request2 {
+            sides.push(("request2", request));
         }
+        sides.extend(self.requests.iter().map(|(k, v)| (k.as_str(), v)));
+        sides
+    }
+
+    /// Compare the normalized response of each side with its snapshot in `dir`, stored as
+    /// `<name>.<side>.snap`.
+    pub async fn snapshot(
+        &self,
+        dir: &Path,
+        name: &str,
+        args: &ExtraArgs,
+        update: bool,
+    ) -> Result<Vec<(&str, SnapshotStatus)>> {
+        let mut results = vec![];
+
+        for (side, request) in self.sides() {
+            let res = request.send(args).await?;
+            let text = res.get_text(&self.response)?;
+            let path = dir.join(format!("{}.{}.snap", name, side));
+
+            results.push((side, check_snapshot(&path, &text, update).await?));
+        }
+
+        Ok(results)
     }
-    pub async fn diff(&self, args: ExtraArgs) -> Result<String> {
-        let res1 = self.request1.send(&args).await?;
-        let res2 = self.request2.send(&args).await?;
 
-        let text1 = res1
-            .get_text(&self.response.skip_headers, &self.response.skip_body)
-            .await?;
-        let text2 = res2
-            .get_text(&self.response.skip_headers, &self.response.skip_body)
-            .await?;
+    /// Send each side `samples` times and return the response profile extended with the
+    /// headers and body paths whose values changed between the identical requests.
+    pub async fn learn(&self, samples: usize, args: &ExtraArgs) -> Result<ResponseProfile> {
+        let mut response = self.response.clone();
+
+        for (_, request) in self.sides() {
+            let first = request.send(args).await?;
+            let body = self.json_body(&first);
+
+            for _ in 1..samples {
+                let res = request.send(args).await?;
+
+                for key in first.headers().keys().chain(res.headers().keys()) {
+                    // headers which are not compared anyway need no skipping
+                    if !response.compares_header(key.as_str())? {
+                        continue;
+                    }
+                    if first.header_values(key.as_str()) != res.header_values(key.as_str()) {
+                        push_unique(&mut response.skip_headers, key.to_string());
+                    }
+                }
+
+                if let (Some(body1), Some(body2)) = (&body, self.json_body(&res)) {
+                    for path in json_path::diff_paths(body1, &body2) {
+                        push_unique(&mut response.skip_body, path);
+                    }
+                }
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// The body without the values that are already skipped, if it is json.
+    fn json_body(&self, res: &ResponseExt) -> Option<serde_json::Value> {
+        let mut body = serde_json::from_str(res.body()).ok()?;
+        for path in &self.response.skip_body {
+            json_path::remove(&mut body, path).ok()?;
+        }
+        Some(body)
+    }
+}
+
+fn push_unique(list: &mut Vec<String>, value: String) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::ResponseSchema, test_server::serve};
+    use hyper::{Body, Response};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn two_sides(url: &str) -> DiffProfile {
+        DiffProfile::new(url.parse().unwrap(), url.parse().unwrap(), vec![])
+    }
+
+    #[tokio::test]
+    async fn learn_finds_the_fields_that_change_between_samples() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let addr = serve(move |_, _| {
+            let n = count.fetch_add(1, Ordering::SeqCst);
+            let body =
+                serde_json::json!({"id": n, "name": "todo", "items": [{"ts": n}, {"ts": n}]});
+            Response::builder()
+                .header("content-type", "application/json")
+                .header("x-request-id", n.to_string())
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        });
+        let mut profile = two_sides(&format!("http://{}/todo", addr));
+        profile.response.skip_body = vec!["id".to_string()];
+
+        let response = profile.learn(3, &ExtraArgs::default()).await.unwrap();
+
+        assert!(response.skip_headers.contains(&"x-request-id".to_string()));
+
+        profile.response.skip_headers = vec!["x-request-*".to_string()];
+        let learned = profile.learn(2, &ExtraArgs::default()).await.unwrap();
+        assert_eq!(learned.skip_headers, ["x-request-*"]);
+        profile.response.skip_headers = vec![];
+        profile.response.only_headers = vec!["content-type".to_string()];
+        let learned = profile.learn(2, &ExtraArgs::default()).await.unwrap();
+        assert!(learned.skip_headers.is_empty());
+        assert!(!response.skip_headers.contains(&"content-type".to_string()));
+        assert_eq!(response.skip_body, ["id", "items[*].ts"]);
+    }
+
+    #[tokio::test]
+    async fn learn_skips_a_scalar_body_as_a_whole() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let addr = serve(move |_, _| {
+            let n = count.fetch_add(1, Ordering::SeqCst);
+            Response::builder()
+                .header("content-type", "application/json")
+                .body(Body::from(n.to_string()))
+                .unwrap()
+        });
+        let profile = two_sides(&format!("http://{}/count", addr));
+
+        let response = profile.learn(2, &ExtraArgs::default()).await.unwrap();
+        assert_eq!(response.skip_body, ["$"]);
+
+        let request = profile.request1.as_ref().unwrap();
+        let res = request.send(&ExtraArgs::default()).await.unwrap();
+        assert_eq!(res.get_body_text(&response.skip_body).unwrap(), "null\n");
+    }
+
+    /// A server taking 100ms per request that records how many requests it served at once.
+    fn slow_server() -> (String, Arc<AtomicUsize>) {
+        let (active, most) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let seen = most.clone();
+        let addr = serve(move |_, _| {
+            let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            active.fetch_sub(1, Ordering::SeqCst);
+            Response::new(Body::from("ok"))
+        });
+        (format!("http://{}/slow", addr), seen)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn sides_are_sent_concurrently_unless_sequential() {
+        let (url, most) = slow_server();
+        let report = two_sides(&url)
+            .diff(&ExtraArgs::default(), 1)
+            .await
+            .unwrap();
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+        assert!(!report.changed);
+        assert!(report.output.contains("request1") && report.output.contains("request2"));
+
+        let (url, most) = slow_server();
+        let mut profile = two_sides(&url);
+        profile.sequential = true;
+        profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+        assert_eq!(most.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn bodies_which_are_not_json_are_schema_violations() {
+        let addr = serve(|parts, _| {
+            let body = match parts.uri.path() {
+                "/json" => r#"{"id": 1}"#,
+                _ => "oops",
+            };
+            Response::new(Body::from(body))
+        });
+        let mut profile = DiffProfile::new(
+            format!("http://{}/json", addr).parse().unwrap(),
+            format!("http://{}/text", addr).parse().unwrap(),
+            vec![],
+        );
+        profile.response.schema = Some(ResponseSchema::Inline(serde_json::json!({
+            "type": "object"
+        })));
+
+        let report = profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+
+        assert!(report.violations["request1"].is_empty());
+        let violations = &report.violations["request2"];
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].path, "$");
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["violations"]["request2"][0]["path"], "$");
+    }
+
+    #[test]
+    fn validate_rejects_ambiguous_request_names() {
+        let request: RequestProfile = "http://localhost/".parse().unwrap();
+        let mut profile = two_sides("http://localhost/");
+        assert!(profile.validate().is_ok());
+
+        profile.requests.insert("eu".to_string(), request.clone());
+        profile.baseline = Some("us".to_string());
+        assert!(profile.validate().is_err());
+        profile.baseline = Some("eu".to_string());
+        assert!(profile.validate().is_ok());
+
+        profile
+            .requests
+            .insert("request1".to_string(), request.clone());
+        let e = profile.validate().unwrap_err();
+        assert_eq!(e.to_string(), "request request1 is defined more than once");
+        profile.requests.shift_remove("request1");
+
+        profile.requests.insert(MAJORITY.to_string(), request);
+        assert!(profile.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn every_request_is_diffed_with_the_baseline() {
+        let addr = serve(|parts, _| {
+            let body = match parts.uri.path() {
+                "/c" => "2",
+                _ => "1",
+            };
+            Response::new(Body::from(body))
+        });
+        let url = |path: &str| format!("http://{}/{}", addr, path).parse().unwrap();
+        let mut profile = two_sides(&format!("http://{}/c", addr));
+        profile.requests.insert("a".to_string(), url("a"));
+        profile.requests.insert("b".to_string(), url("b"));
+
+        let report = profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+        assert_eq!(report.baseline, "request1");
+        assert_eq!(
+            report.diffs.keys().collect::<Vec<_>>(),
+            ["request2", "a", "b"]
+        );
+        assert!(report.diffs["request2"].is_empty());
+        assert!(!report.diffs["a"].is_empty());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["stats"]["a"]["body_size"]["min"], 1);
+        assert_eq!(json["stats"]["request1"]["count"], 1);
+
+        profile.baseline = Some("b".to_string());
+        let report = profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+        assert_eq!(report.baseline, "b");
+        assert!(report.diffs["a"].is_empty());
+        assert!(report.changed);
+        assert_eq!(report.failure().as_deref(), Some("responses differ"));
+
+        let mut profile = two_sides(&format!("http://{}/a", addr));
+        profile.response.skip_headers = vec!["date".to_string()];
+        let report = profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+        assert_eq!(report.failure(), None);
+    }
+
+    #[tokio::test]
+    async fn matrix_cases_render_every_request() {
+        let mut profile = two_sides("http://old.test/todos/{{ id }}");
+        profile.request2 = Some(
+            "http://new.test/todos/{{ id }}?lang={{ lang }}"
+                .parse()
+                .unwrap(),
+        );
+        profile.matrix = Some(
+            serde_yaml::from_str("values:\n  - {id: 1, lang: en}\n  - {id: 2, lang: de}\n")
+                .unwrap(),
+        );
+
+        let cases = profile.cases().await.unwrap();
+        let names = cases
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["id=1, lang=en", "id=2, lang=de"]);
+
+        let (_, case) = &cases[1];
+        assert!(case.matrix.is_none());
+        assert_eq!(
+            case.request1.as_ref().unwrap().url.as_str(),
+            "http://old.test/todos/2"
+        );
+        let request2 = case.request2.as_ref().unwrap();
+        assert_eq!(request2.url.as_str(), "http://new.test/todos/2");
+        assert_eq!(request2.params, Some(serde_json::json!({"lang": "de"})));
+    }
+
+    #[tokio::test]
+    async fn majority_baseline_is_the_response_most_requests_match() {
+        let addr = serve(|parts, _| {
+            Response::builder()
+                .header("x-served-by", parts.uri.path())
+                .body(Body::from(parts.uri.path()[..2].to_string()))
+                .unwrap()
+        });
+        let url = |path: &str| format!("http://{}{}", addr, path).parse().unwrap();
+        let mut profile = two_sides(&format!("http://{}/a", addr));
+        profile.request2 = Some(url("/b1"));
+        profile.requests.insert("c".to_string(), url("/b2"));
+        profile.baseline = Some(MAJORITY.to_string());
+
+        // the bodies of request2 and c match, but their headers do not
+        let report = profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+        assert_eq!(report.baseline, "request1");
 
-        text_diff(text1, text2)
+        profile.response.skip_headers = vec!["x-served-by".to_string()];
+        let report = profile.diff(&ExtraArgs::default(), 1).await.unwrap();
+        assert_eq!(report.baseline, "request2");
+        assert!(report.diffs["c"].is_empty());
+        assert!(!report.diffs["request1"].is_empty());
     }
 }
@@ -1,14 +1,22 @@
 use anyhow::{Context, Result};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
-use crate::{is_default, LoadYaml, RequestProfile, ResponseProfile, ValidateConfig};
+use crate::{
+    config::matrix::{case_name, Matrix},
+    is_default,
+    snapshot::{check_snapshot, SnapshotStatus},
+    template::Vars,
+    Assertions, ExtraArgs, LoadYaml, RequestProfile, ResponseExt, ResponseProfile, ValidateConfig,
+    WorkflowStep,
+};
 
 /// Represents the configuration for performing diffs.
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ReqConfig {
     #[serde(flatten)]
-    pub profiles: HashMap<String, ReqProfile>,
+    pub profiles: IndexMap<String, ReqProfile>,
 }
 impl LoadYaml for ReqConfig {}
 
@@ -25,7 +33,7 @@ impl ValidateConfig for ReqConfig {
 }
 
 impl ReqConfig {
-    pub fn new(profiles: HashMap<String, ReqProfile>) -> Self {
+    pub fn new(profiles: IndexMap<String, ReqProfile>) -> Self {
         Self { profiles }
     }
 
@@ -35,18 +43,49 @@ impl ReqConfig {
 }
 
 /// Represents a diff profile.
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ReqProfile {
+    /// Initial variables for `{{ name }}` placeholders.
+    #[serde(skip_serializing_if = "HashMap::is_empty", default)]
+    pub vars: Vars,
+    /// Run the profile once per row, with the row added to `vars`.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub matrix: Option<Matrix>,
+    /// Requests sent in order before `request`, e.g. to log in and extract a token.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub steps: Vec<WorkflowStep>,
     pub request: RequestProfile,
     #[serde(skip_serializing_if = "is_default", default)]
     pub response: ResponseProfile,
+    #[serde(
+        rename = "assert",
+        skip_serializing_if = "Assertions::is_empty",
+        default
+    )]
+    pub assertions: Assertions,
 }
 
 impl ValidateConfig for ReqProfile {
     fn validate(&self) -> Result<()> {
+        for (i, step) in self.steps.iter().enumerate() {
+            step.validate().context(format!(
+                "step {} failed to validate",
+                step.name.clone().unwrap_or_else(|| i.to_string())
+            ))?;
+        }
+
         self.request
             .validate()
             .context("request failed to validate")?;
+        if let Some(matrix) = &self.matrix {
+            matrix.validate().context("matrix failed to validate")?;
+        }
+        self.response
+            .validate()
+            .context("response failed to validate")?;
+        self.assertions
+            .validate()
+            .context("assert failed to validate")?;
 
         Ok(())
     }
@@ -55,8 +94,69 @@ impl ValidateConfig for ReqProfile {
 impl ReqProfile {
     pub fn new(request: RequestProfile, skip_headers: Vec<String>) -> Self {
         Self {
+            vars: Vars::new(),
+            matrix: None,
+            steps: vec![],
             request,
             response: ResponseProfile::new(skip_headers, vec![]),
+            assertions: Assertions::default(),
+        }
+    }
+
+    /// Run the steps in order, then send the request with the collected variables.
+    pub async fn send(&self, extra: &ExtraArgs) -> Result<ResponseExt> {
+        self.resolve(extra).await?.send(extra).await
+    }
+
+    /// The request with every placeholder filled in. Steps are sent to collect their variables.
+    pub async fn resolve(&self, extra: &ExtraArgs) -> Result<RequestProfile> {
+        if self.steps.is_empty() && self.vars.is_empty() {
+            return Ok(self.request.clone());
+        }
+
+        let mut vars = self.vars.clone();
+        for (i, step) in self.steps.iter().enumerate() {
+            step.run(&mut vars, &extra.without_overrides())
+                .await
+                .context(format!(
+                    "step {} failed",
+                    step.name.clone().unwrap_or_else(|| i.to_string())
+                ))?;
         }
+
+        self.request.render(&vars)
+    }
+
+    /// One profile per row of the matrix, named after the row.
+    pub async fn cases(&self) -> Result<Vec<(String, ReqProfile)>> {
+        let Some(matrix) = &self.matrix else {
+            return Ok(vec![]);
+        };
+
+        Ok(matrix
+            .rows()
+            .await?
+            .into_iter()
+            .map(|row| {
+                let mut case = self.clone();
+                case.matrix = None;
+                let name = case_name(&row);
+                case.vars.extend(row);
+                (name, case)
+            })
+            .collect())
+    }
+
+    /// Compare the normalized response with the snapshot at `path`.
+    pub async fn snapshot(
+        &self,
+        path: &Path,
+        extra: &ExtraArgs,
+        update: bool,
+    ) -> Result<SnapshotStatus> {
+        let res = self.send(extra).await?;
+        let text = res.get_text(&self.response)?;
+
+        check_snapshot(path, &text, update).await
     }
 }
@@ -0,0 +1,155 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+};
+
+use anyhow::{anyhow, Result};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use tokio::{fs, sync::Mutex};
+
+/// Jars in use by this run, by path, so requests sharing a file also share its cookies.
+static JARS: OnceLock<Mutex<HashMap<PathBuf, CookieJar>>> = OnceLock::new();
+
+/// A cookie jar which is loaded from and persisted to a file, so sessions survive between runs.
+#[derive(Debug, Clone)]
+pub struct CookieJar {
+    path: PathBuf,
+    store: Arc<CookieStoreMutex>,
+    /// held while the jar is written, so concurrent saves can not interleave
+    saving: Arc<Mutex<()>>,
+}
+
+impl CookieJar {
+    /// Load the jar from `path`. A missing file yields an empty jar.
+    pub async fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let store = match fs::read(&path).await {
+            Ok(content) => CookieStore::load_json(Cursor::new(content))
+                .map_err(|e| anyhow!("failed to load cookie jar {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CookieStore::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            path,
+            store: Arc::new(CookieStoreMutex::new(store)),
+            saving: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// The jar for `path` shared by every request of this run. It is loaded on first use and
+    /// written back by [`save_all`].
+    pub async fn shared(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let mut jars = JARS.get_or_init(Default::default).lock().await;
+        if let Some(jar) = jars.get(&path) {
+            return Ok(jar.clone());
+        }
+
+        let jar = Self::load(path.clone()).await?;
+        jars.insert(path, jar.clone());
+        Ok(jar)
+    }
+
+    pub fn provider(&self) -> Arc<CookieStoreMutex> {
+        self.store.clone()
+    }
+
+    /// Write every cookie back to the file, including session cookies, the same way a browser
+    /// keeps them for as long as it is open.
+    pub async fn save(&self) -> Result<()> {
+        let _saving = self.saving.lock().await;
+        let mut content = Vec::new();
+        self.store
+            .lock()
+            .map_err(|_| anyhow!("cookie jar lock poisoned"))?
+            .save_incl_expired_and_nonpersistent_json(&mut content)
+            .map_err(|e| anyhow!("failed to save cookie jar {}: {}", self.path.display(), e))?;
+
+        fs::write(&self.path, content).await?;
+
+        Ok(())
+    }
+}
+
+/// Persist every jar used by this run. Call it once the requests are done.
+pub async fn save_all() -> Result<()> {
+    let Some(jars) = JARS.get() else {
+        return Ok(());
+    };
+
+    for jar in jars.lock().await.values() {
+        jar.save().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server::serve, ExtraArgs, RequestProfile};
+    use hyper::{Body, Response};
+    use reqwest::{header::HeaderMap, Method};
+
+    fn cookie_server() -> std::net::SocketAddr {
+        serve(|parts, _| {
+            let name = parts.uri.path().trim_start_matches('/').to_string();
+            let cookie = parts
+                .headers
+                .get("cookie")
+                .map(|v| v.to_str().unwrap().to_string())
+                .unwrap_or_default();
+            Response::builder()
+                .header("set-cookie", format!("{}=1; Path=/", name))
+                .body(Body::from(cookie))
+                .unwrap()
+        })
+    }
+
+    fn request(addr: std::net::SocketAddr, path: &str, jar: &std::path::Path) -> RequestProfile {
+        let url = format!("http://{}/{}", addr, path).parse().unwrap();
+        let mut request = RequestProfile::new(url, Method::GET, None, None, HeaderMap::new());
+        request.cookie_jar = Some(jar.to_string_lossy().to_string());
+        request
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_the_jar() {
+        let addr = cookie_server();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jar.json");
+        let extra = ExtraArgs::default();
+
+        let (a, b) = (request(addr, "a", &path), request(addr, "b", &path));
+        tokio::try_join!(a.send(&extra), b.send(&extra)).unwrap();
+        let res = request(addr, "c", &path).send(&extra).await.unwrap();
+
+        let mut cookies = res.body().split("; ").collect::<Vec<_>>();
+        cookies.sort();
+        assert_eq!(cookies, ["a=1", "b=1"]);
+    }
+
+    #[tokio::test]
+    async fn saved_jar_is_loaded_again() {
+        let addr = cookie_server();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("jar.json");
+        let extra = ExtraArgs::default();
+
+        request(addr, "a", &path).send(&extra).await.unwrap();
+        CookieJar::shared(&path)
+            .await
+            .unwrap()
+            .save()
+            .await
+            .unwrap();
+
+        let jar = CookieJar::load(&path).await.unwrap();
+        let store = jar.provider();
+        let store = store.lock().unwrap();
+        assert_eq!(store.get("127.0.0.1", "/", "a").unwrap().value(), "1");
+    }
+}
@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Result;
+use serde::Serialize;
+use similar::TextDiff;
+
+use crate::{ResponseExt, ResponseProfile};
+
+/// What part of two responses differs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difference {
+    Status,
+    Headers,
+    Body,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Difference::Status => "status",
+            Difference::Headers => "headers",
+            Difference::Body => "body",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The differences between two responses, by section. Sections that match are empty.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResponseDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<HeaderChange>,
+    /// unified diff of the bodies
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+    /// the compared bodies, for rendering the diff
+    #[serde(skip)]
+    pub body_text: (String, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct StatusChange {
+    pub old: u16,
+    pub new: u16,
+}
+
+/// A header whose values differ. Names are lowercase, and the values of repeated headers are
+/// compared regardless of their order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HeaderChange {
+    pub name: String,
+    /// empty when the header was added
+    pub old: Vec<String>,
+    /// empty when the header was removed
+    pub new: Vec<String>,
+}
+
+/// The parts of a response that are compared: the status, the headers a `ResponseProfile`
+/// compares by name, and the body without the skipped fields.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResponseSections {
+    pub status: u16,
+    /// sorted values by lowercase name
+    pub headers: BTreeMap<String, Vec<String>>,
+    pub body: String,
+}
+
+impl ResponseSections {
+    pub fn new(res: &ResponseExt, response: &ResponseProfile) -> Result<Self> {
+        let mut headers = BTreeMap::new();
+        for name in res.get_headers() {
+            if response.compares_header(&name)? {
+                let values = res.header_values(&name);
+                headers.insert(name, values);
+            }
+        }
+
+        Ok(Self {
+            status: res.status().as_u16(),
+            headers,
+            body: res.get_body_text(&response.skip_body)?,
+        })
+    }
+
+    /// Parse the text of [`ResponseExt::get_text`], e.g. a snapshot: a status line, one line
+    /// per header value, a blank line and the body.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (head, body) = text.split_once("\n\n")?;
+        let mut lines = head.lines();
+        let status = lines.next()?.split_whitespace().nth(1)?.parse().ok()?;
+
+        let mut headers = BTreeMap::<_, Vec<_>>::new();
+        for line in lines {
+            let (name, value) = line
+                .split_once(": ")
+                .or_else(|| Some((line.strip_suffix(':')?, "")))?;
+            headers
+                .entry(name.to_string())
+                .or_default()
+                .push(value.to_string());
+        }
+
+        Some(Self {
+            status,
+            headers,
+            body: body.to_string(),
+        })
+    }
+}
+
+impl ResponseDiff {
+    /// Compare `old` with `new`, leaving out what `response` says to skip.
+    pub fn new(old: &ResponseExt, new: &ResponseExt, response: &ResponseProfile) -> Result<Self> {
+        Ok(Self::between(
+            &ResponseSections::new(old, response)?,
+            &ResponseSections::new(new, response)?,
+        ))
+    }
+
+    pub fn between(old: &ResponseSections, new: &ResponseSections) -> Self {
+        let status = (old.status != new.status).then_some(StatusChange {
+            old: old.status,
+            new: new.status,
+        });
+
+        let mut names = old
+            .headers
+            .keys()
+            .chain(new.headers.keys())
+            .collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        let none = vec![];
+        let headers = names
+            .into_iter()
+            .filter_map(|name| {
+                let old = old.headers.get(name).unwrap_or(&none);
+                let new = new.headers.get(name).unwrap_or(&none);
+                (old != new).then(|| HeaderChange {
+                    name: name.to_string(),
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            })
+            .collect();
+
+        let body_text = (old.body.clone(), new.body.clone());
+        let body = (body_text.0 != body_text.1).then(|| {
+            TextDiff::from_lines(&body_text.0, &body_text.1)
+                .unified_diff()
+                .context_radius(3)
+                .to_string()
+        });
+
+        Self {
+            status,
+            headers,
+            body,
+            body_text,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.differences().is_empty()
+    }
+
+    /// The sections that differ.
+    pub fn differences(&self) -> Vec<Difference> {
+        let mut differences = vec![];
+        if self.status.is_some() {
+            differences.push(Difference::Status);
+        }
+        if !self.headers.is_empty() {
+            differences.push(Difference::Headers);
+        }
+        if self.body.is_some() {
+            differences.push(Difference::Body);
+        }
+        differences
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{
+        header::{HeaderMap, HeaderValue},
+        StatusCode, Version,
+    };
+    use std::time::Duration;
+
+    fn response(status: u16, headers: &[(&'static str, &'static str)], body: &str) -> ResponseExt {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        ResponseExt::new(
+            Version::HTTP_11,
+            StatusCode::from_u16(status).unwrap(),
+            map,
+            body.to_string(),
+            Duration::ZERO,
+        )
+    }
+
+    #[test]
+    fn sections_parse_back_from_the_response_text() {
+        let res = response(
+            404,
+            &[
+                ("content-type", "application/json"),
+                ("vary", "b"),
+                ("vary", "a"),
+                ("x-id", "1"),
+            ],
+            r#"{"id": 1, "name": "a"}"#,
+        );
+        let profile = ResponseProfile::new(vec!["x-id".to_string()], vec!["id".to_string()]);
+
+        let sections = ResponseSections::new(&res, &profile).unwrap();
+        assert_eq!(sections.status, 404);
+        assert_eq!(sections.headers["vary"], ["a", "b"]);
+        assert!(!sections.headers.contains_key("x-id"));
+        assert_eq!(sections.body, "{\n  \"name\": \"a\"\n}\n");
+
+        let text = res.get_text(&profile).unwrap();
+        assert_eq!(ResponseSections::parse(&text), Some(sections));
+        assert_eq!(ResponseSections::parse("just text\n"), None);
+    }
+
+    #[test]
+    fn diff_lists_each_section_that_differs() {
+        let profile = ResponseProfile::default();
+        let old = response(200, &[("etag", "1"), ("x-old", "y")], "a\nb\n");
+        let new = response(201, &[("etag", "2"), ("x-new", "y")], "a\nc\n");
+
+        let diff = ResponseDiff::new(&old, &new, &profile).unwrap();
+        assert_eq!(diff.status, Some(StatusChange { old: 200, new: 201 }));
+        let names = diff
+            .headers
+            .iter()
+            .map(|h| h.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["etag", "x-new", "x-old"]);
+        assert_eq!(diff.headers[1].old, Vec::<String>::new());
+        assert!(diff.body.as_ref().unwrap().contains("-b\n+c\n"));
+        assert_eq!(
+            diff.differences(),
+            [Difference::Status, Difference::Headers, Difference::Body]
+        );
+
+        assert!(ResponseDiff::new(&old, &old, &profile).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Write;
+
+use anyhow::Result;
+use reqwest::Method;
+use serde_json::Value;
+
+use crate::{ExtraArgs, RequestProfile};
+
+impl RequestProfile {
+    /// Render the request exactly as `send` would build it as a copy-pasteable curl command.
+    /// The empty json body generated for requests without a body is left out.
+    pub fn to_curl(&self, extra: &ExtraArgs) -> Result<String> {
+        let (headers, body, query) = self.generate(extra)?;
+
+        let mut url = self.url.clone();
+        if let Some(query) = query.as_object().filter(|q| !q.is_empty()) {
+            let mut pairs = url.query_pairs_mut();
+            for (key, value) in query {
+                match value {
+                    Value::String(v) => pairs.append_pair(key, v),
+                    v => pairs.append_pair(key, &v.to_string()),
+                };
+            }
+        }
+
+        // curl defaults to POST when data is given
+        let has_body = !body.is_empty() && body != "{}";
+        let default_method = if has_body { Method::POST } else { Method::GET };
+
+        let mut output = String::from("curl");
+        if self.method != default_method {
+            write!(&mut output, " -X {}", self.method)?;
+        }
+        write!(&mut output, " {}", shell_words::quote(url.as_str()))?;
+
+        for (key, value) in &headers {
+            let header = format!("{}: {}", key, String::from_utf8_lossy(value.as_bytes()));
+            write!(&mut output, " \\\n  -H {}", shell_words::quote(&header))?;
+        }
+
+        if has_body {
+            write!(
+                &mut output,
+                " \\\n  --data-raw {}",
+                shell_words::quote(&body)
+            )?;
+        }
+
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::parse_curl;
+    use reqwest::header::{HeaderMap, HeaderValue};
+    use serde_json::json;
+
+    #[test]
+    fn get_without_body_has_no_data() {
+        let request: RequestProfile = "https://example.com/todos?a=1".parse().unwrap();
+        assert_eq!(
+            request.to_curl(&ExtraArgs::default()).unwrap(),
+            "curl 'https://example.com/todos?a=1' \\\n  -H 'content-type: application/json'"
+        );
+    }
+
+    #[test]
+    fn round_trips_through_parse_curl() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers.insert("x-note", HeaderValue::from_static("it's"));
+        let request = RequestProfile::new(
+            "https://example.com/todos".parse().unwrap(),
+            Method::PATCH,
+            Some(json!({"q": "a b", "n": 1})),
+            Some(json!({"title": "x"})),
+            headers,
+        );
+
+        let cmd = request.to_curl(&ExtraArgs::default()).unwrap();
+        assert!(cmd.starts_with("curl -X PATCH "), "{}", cmd);
+
+        let parsed = parse_curl(&cmd).unwrap();
+        assert_eq!(parsed.method, Method::PATCH);
+        assert_eq!(parsed.url.as_str(), "https://example.com/todos");
+        assert_eq!(parsed.params, Some(json!({"q": "a b", "n": "1"})));
+        assert_eq!(parsed.body, Some(json!({"title": "x"})));
+        assert_eq!(parsed.headers["x-note"], "it's");
+    }
+}
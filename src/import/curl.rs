@@ -0,0 +1,316 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde_json::json;
+use url::Url;
+
+use super::parse_body;
+use crate::RequestProfile;
+
+/// Short options which take no value. They can be combined, e.g. `-sSL`.
+const SHORT_FLAGS: &str = "sSLkivfgG#";
+
+/// Long options which take no value and do not change the request.
+const IGNORED_FLAGS: &[&str] = &[
+    "--compressed",
+    "--silent",
+    "--show-error",
+    "--location",
+    "--insecure",
+    "--include",
+    "--verbose",
+    "--fail",
+    "--globoff",
+    "--http1.1",
+    "--http2",
+];
+
+/// Parse a curl command line, e.g. one copied from the browser devtools, into a request
+/// profile. Form fields (`-F`) are sent url encoded since multipart bodies are not supported.
+pub fn parse_curl(cmd: &str) -> Result<RequestProfile> {
+    let cmd = cmd.replace("\\\r\n", " ").replace("\\\n", " ");
+    let words = shell_words::split(&cmd)?;
+    let mut words = words.into_iter();
+
+    match words.next() {
+        Some(v) if v == "curl" => {}
+        _ => bail!("Not a curl command: {}", cmd.trim()),
+    }
+
+    let mut url = None;
+    let mut method = None;
+    let mut headers = HeaderMap::new();
+    let mut data = vec![];
+    let mut form = vec![];
+    let mut get = false;
+
+    while let Some(word) = words.next() {
+        // `-XPOST` is the same as `-X POST`, and flags may come first, as in `-sX POST`
+        let (option, attached) = match word.strip_prefix('-') {
+            Some(rest) if !rest.starts_with('-') && rest.chars().count() > 1 => {
+                let mut chars = rest.chars();
+                let mut flag = None;
+                for c in chars.by_ref() {
+                    match c {
+                        'G' => get = true,
+                        'I' => method = Some(Method::HEAD),
+                        c if SHORT_FLAGS.contains(c) => {}
+                        c => {
+                            flag = Some(c);
+                            break;
+                        }
+                    }
+                }
+                let Some(flag) = flag else {
+                    continue;
+                };
+                let attached = chars.as_str();
+                (
+                    format!("-{}", flag),
+                    (!attached.is_empty()).then(|| attached.to_string()),
+                )
+            }
+            _ => (word.clone(), None),
+        };
+
+        let mut value = || {
+            attached
+                .clone()
+                .or_else(|| words.next())
+                .ok_or_else(|| anyhow!("Missing value for {}", option))
+        };
+
+        match option.as_str() {
+            "-X" | "--request" => method = Some(Method::from_str(&value()?.to_uppercase())?),
+            "-H" | "--header" => {
+                let header = value()?;
+                let (key, val) = header
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("Invalid header: {}", header))?;
+                if !key.eq_ignore_ascii_case("content-length") {
+                    headers.append(
+                        HeaderName::from_str(key.trim())?,
+                        HeaderValue::from_str(val.trim())?,
+                    );
+                }
+            }
+            "-d" | "--data" | "--data-ascii" | "--data-binary" => {
+                let v = value()?;
+                if v.starts_with('@') {
+                    bail!("Reading data from a file is not supported: {}", v);
+                }
+                data.push(Data::Raw(v));
+            }
+            "--data-raw" => data.push(Data::Raw(value()?)),
+            "--data-urlencode" => data.push(Data::Encode(value()?)),
+            "-F" | "--form" | "--form-string" => {
+                let v = value()?;
+                let (key, val) = v
+                    .split_once('=')
+                    .ok_or_else(|| anyhow!("Invalid form field: {}", v))?;
+                if option != "--form-string" && (val.starts_with('@') || val.starts_with('<')) {
+                    bail!("Uploading files is not supported: {}", v);
+                }
+                form.push((key.to_string(), val.to_string()));
+            }
+            "-u" | "--user" => {
+                let credentials = value()?;
+                headers.insert(
+                    "authorization",
+                    HeaderValue::from_str(&format!("Basic {}", STANDARD.encode(credentials)))?,
+                );
+            }
+            "-b" | "--cookie" => {
+                let cookie = value()?;
+                if !cookie.contains('=') {
+                    bail!("Reading cookies from a file is not supported: {}", cookie);
+                }
+                headers.append("cookie", HeaderValue::from_str(&cookie)?);
+            }
+            "-A" | "--user-agent" => {
+                headers.insert("user-agent", HeaderValue::from_str(&value()?)?);
+            }
+            "-e" | "--referer" => {
+                headers.insert("referer", HeaderValue::from_str(&value()?)?);
+            }
+            "-G" | "--get" => get = true,
+            "-I" | "--head" => method = Some(Method::HEAD),
+            "-o" | "--output" | "-m" | "--max-time" | "--connect-timeout" => {
+                value()?;
+            }
+            "--url" => url = Some(value()?),
+            v if IGNORED_FLAGS.contains(&v) => {}
+            v if v.len() == 2 && v[1..].chars().all(|c| SHORT_FLAGS.contains(c)) => {}
+            v if v.starts_with('-') => bail!("Unsupported curl option: {}", v),
+            _ => url = Some(word),
+        }
+    }
+
+    let mut url = Url::parse(&url.ok_or_else(|| anyhow!("No url found in curl command"))?)?;
+    let mut params = json!({});
+    for (key, value) in url.query_pairs() {
+        params[&*key] = json!(value);
+    }
+    url.set_query(None);
+
+    let data = data.iter().map(Data::encode).collect::<Vec<_>>().join("&");
+    let body = if get {
+        for (key, value) in url::form_urlencoded::parse(data.as_bytes()) {
+            params[&*key] = json!(value);
+        }
+        None
+    } else if !form.is_empty() {
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        let mut body = json!({});
+        for (key, value) in form {
+            body[key] = json!(value);
+        }
+        Some(body)
+    } else if !data.is_empty() {
+        Some(parse_body(&data, &mut headers)?)
+    } else {
+        None
+    };
+
+    let method = method.unwrap_or(if body.is_some() {
+        Method::POST
+    } else {
+        Method::GET
+    });
+
+    Ok(RequestProfile::new(
+        url,
+        method,
+        Some(params),
+        body,
+        headers,
+    ))
+}
+
+enum Data {
+    /// Already url encoded, or a json document.
+    Raw(String),
+    /// `name=content` where content still needs to be url encoded.
+    Encode(String),
+}
+
+impl Data {
+    fn encode(&self) -> String {
+        match self {
+            Data::Raw(v) => v.clone(),
+            Data::Encode(v) => match v.split_once('=') {
+                Some((key, val)) => format!("{}={}", key, urlencode(val)),
+                None => urlencode(v),
+            },
+        }
+    }
+}
+
+fn urlencode(s: &str) -> String {
+    url::form_urlencoded::byte_serialize(s.as_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_devtools_command() {
+        let request = parse_curl(
+            r#"curl 'https://example.com/api/items?page=2' \
+  -H 'accept: application/json' \
+  -H 'Content-Length: 12' \
+  -H 'content-type: application/json' \
+  --data-raw '{"name":"x"}' \
+  --compressed"#,
+        )
+        .unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url.as_str(), "https://example.com/api/items");
+        assert_eq!(request.params, Some(json!({"page": "2"})));
+        assert_eq!(request.body, Some(json!({"name": "x"})));
+        assert_eq!(request.headers["accept"], "application/json");
+        assert!(request.headers.get("content-length").is_none());
+    }
+
+    #[test]
+    fn attached_values_and_combined_flags() {
+        let request =
+            parse_curl("curl -sSL -XPUT -Hx-a:1 -uuser:pass https://example.com").unwrap();
+
+        assert_eq!(request.method, Method::PUT);
+        assert_eq!(request.headers["x-a"], "1");
+        assert_eq!(request.headers["authorization"], "Basic dXNlcjpwYXNz");
+    }
+
+    #[test]
+    fn flags_may_precede_an_option_in_a_cluster() {
+        let request = parse_curl("curl -sX POST https://example.com").unwrap();
+        assert_eq!(request.method, Method::POST);
+
+        let request = parse_curl("curl -sSLXPUT -sH 'x-a: 1' https://example.com").unwrap();
+        assert_eq!(request.method, Method::PUT);
+        assert_eq!(request.headers["x-a"], "1");
+
+        let request = parse_curl("curl -sd a=1 https://example.com").unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.body, Some(json!({"a": "1"})));
+
+        let request = parse_curl("curl -sI https://example.com").unwrap();
+        assert_eq!(request.method, Method::HEAD);
+    }
+
+    #[test]
+    fn attached_value_may_start_with_multibyte_char() {
+        let request = parse_curl("curl -Aé-agent -Hx-name:é https://example.com").unwrap();
+
+        assert_eq!(
+            request.headers["user-agent"].as_bytes(),
+            "é-agent".as_bytes()
+        );
+        assert_eq!(request.headers["x-name"].as_bytes(), "é".as_bytes());
+        assert!(parse_curl("curl -é https://example.com").is_err());
+        assert!(parse_curl("curl -éx https://example.com").is_err());
+    }
+
+    #[test]
+    fn get_moves_data_into_the_query() {
+        let request =
+            parse_curl("curl -G https://example.com/search -d q=a --data-urlencode 'name=b c'")
+                .unwrap();
+
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.params, Some(json!({"q": "a", "name": "b c"})));
+        assert_eq!(request.body, None);
+    }
+
+    #[test]
+    fn form_fields_are_url_encoded() {
+        let request = parse_curl("curl https://example.com -F a=1 -F b=2").unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.body, Some(json!({"a": "1", "b": "2"})));
+        assert_eq!(
+            request.headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_input() {
+        assert!(parse_curl("wget https://example.com").is_err());
+        assert!(parse_curl("curl -d @body.json https://example.com").is_err());
+        assert!(parse_curl("curl -F file=@a.png https://example.com").is_err());
+        assert!(parse_curl("curl --proxy x https://example.com").is_err());
+        assert!(parse_curl("curl -H").is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use anyhow::Result;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde::Deserialize;
+use serde_json::json;
+use url::Url;
+
+use super::parse_body;
+use crate::RequestProfile;
+
+/// Headers which are set by the client itself and should not be copied into a profile.
+const SKIP_HEADERS: &[&str] = &["host", "content-length", "connection", "accept-encoding"];
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+/// A request/response pair captured in a HAR file. Only the request is used.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HarEntry {
+    pub request: HarRequest,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<HarPair>,
+    #[serde(default)]
+    pub post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HarPair {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HarPostData {
+    #[serde(default)]
+    pub mime_type: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub params: Vec<HarPair>,
+}
+
+/// Read the entries of a HAR document.
+pub fn load_har(content: &str) -> Result<Vec<HarEntry>> {
+    let har: Har = serde_json::from_str(content)?;
+    Ok(har.log.entries)
+}
+
+impl HarEntry {
+    pub fn method(&self) -> Result<Method> {
+        Ok(Method::from_str(&self.request.method.to_uppercase())?)
+    }
+
+    pub fn url(&self) -> Result<Url> {
+        Ok(Url::parse(&self.request.url)?)
+    }
+
+    pub fn to_request(&self) -> Result<RequestProfile> {
+        let mut url = self.url()?;
+        let mut params = json!({});
+        for (key, value) in url.query_pairs() {
+            params[&*key] = json!(value);
+        }
+        url.set_query(None);
+
+        let mut headers = HeaderMap::new();
+        for HarPair { name, value } in &self.request.headers {
+            // http/2 pseudo headers such as `:authority`
+            if name.starts_with(':') || SKIP_HEADERS.contains(&name.to_lowercase().as_str()) {
+                continue;
+            }
+            headers.append(HeaderName::from_str(name)?, HeaderValue::from_str(value)?);
+        }
+
+        let body = match &self.request.post_data {
+            Some(data) => {
+                if !data.mime_type.is_empty() {
+                    headers.insert("content-type", HeaderValue::from_str(&data.mime_type)?);
+                }
+                let text = match &data.text {
+                    Some(text) => text.clone(),
+                    None => url::form_urlencoded::Serializer::new(String::new())
+                        .extend_pairs(data.params.iter().map(|p| (&p.name, &p.value)))
+                        .finish(),
+                };
+                Some(parse_body(&text, &mut headers)?)
+            }
+            None => None,
+        };
+
+        Ok(RequestProfile::new(
+            url,
+            self.method()?,
+            Some(params),
+            body,
+            headers,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAR: &str = r#"{"log": {"entries": [
+        {"request": {"method": "post", "url": "https://example.com/api/todos?draft=1",
+            "headers": [
+                {"name": ":authority", "value": "example.com"},
+                {"name": "Content-Length", "value": "12"},
+                {"name": "x-token", "value": "abc"}
+            ],
+            "postData": {"mimeType": "application/json", "text": "{\"title\":\"x\"}"}}},
+        {"request": {"method": "POST", "url": "https://example.com/login",
+            "postData": {"mimeType": "application/x-www-form-urlencoded",
+                "params": [{"name": "user", "value": "a b"}]}}},
+        {"request": {"method": "GET", "url": "not a url"}}
+    ]}}"#;
+
+    #[test]
+    fn entries_become_requests() {
+        let entries = load_har(HAR).unwrap();
+        assert_eq!(entries.len(), 3);
+
+        let request = entries[0].to_request().unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url.as_str(), "https://example.com/api/todos");
+        assert_eq!(request.params, Some(json!({"draft": "1"})));
+        assert_eq!(request.body, Some(json!({"title": "x"})));
+        assert_eq!(request.headers.len(), 2);
+        assert_eq!(request.headers["x-token"], "abc");
+
+        let request = entries[1].to_request().unwrap();
+        assert_eq!(request.body, Some(json!({"user": "a b"})));
+        assert_eq!(
+            request.headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+
+        assert!(entries[2].to_request().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{collections::HashMap, str::FromStr, sync::OnceLock};
+
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::RequestProfile;
+
+use super::parse_body;
+
+/// A request from an access log, one json object per line.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogEntry {
+    #[serde(default = "default_method")]
+    pub method: String,
+    /// path, optionally with the query string
+    pub path: String,
+    /// query parameters, as an object or a query string
+    #[serde(default)]
+    pub query: Option<Value>,
+    /// body, as an object or the raw request data
+    #[serde(default)]
+    pub body: Option<Value>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// Parse a JSONL access log. Empty lines are ignored.
+pub fn load_log(content: &str) -> Result<Vec<LogEntry>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).context(format!("line {}", i + 1)))
+        .collect()
+}
+
+impl LogEntry {
+    /// The request sent to `base`, the scheme, host and optional path prefix of an environment.
+    pub fn to_request(&self, base: &Url) -> Result<RequestProfile> {
+        let url = Url::parse(&format!(
+            "{}/{}",
+            base.as_str().trim_end_matches('/'),
+            self.path.trim_start_matches('/')
+        ))?;
+
+        let mut params = json!({});
+        for (key, value) in url.query_pairs() {
+            params[&*key] = json!(value);
+        }
+        match &self.query {
+            Some(Value::Object(query)) => {
+                for (key, value) in query {
+                    params[key] = value.clone();
+                }
+            }
+            Some(Value::String(query)) => {
+                for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+                    params[&*key] = json!(value);
+                }
+            }
+            Some(Value::Null) | None => {}
+            Some(v) => bail!("query must be an object or a string, got {}", v),
+        }
+        let mut url = url;
+        url.set_query(None);
+
+        let mut headers = HeaderMap::new();
+        for (key, value) in &self.headers {
+            headers.append(HeaderName::from_str(key)?, HeaderValue::from_str(value)?);
+        }
+
+        let body = match &self.body {
+            Some(body @ Value::Object(_)) => {
+                headers.insert("content-type", HeaderValue::from_static("application/json"));
+                Some(body.clone())
+            }
+            Some(Value::String(data)) if !data.is_empty() => Some(parse_body(data, &mut headers)?),
+            Some(Value::String(_)) | Some(Value::Null) | None => None,
+            Some(v) => bail!("body must be an object or a string, got {}", v),
+        };
+        // the content type of a body is set above, a request without one needs none
+        if body.is_none() {
+            headers.remove("content-type");
+        }
+
+        let method = Method::from_str(&self.method.to_uppercase())
+            .map_err(|_| anyhow!("invalid method {}", self.method))?;
+        Ok(RequestProfile::new(
+            url,
+            method,
+            Some(params),
+            body,
+            headers,
+        ))
+    }
+
+    /// Method and path with ids replaced by `{id}`, so requests to the same endpoint group
+    /// together.
+    pub fn endpoint(&self) -> String {
+        static ID: OnceLock<Regex> = OnceLock::new();
+        let id = ID.get_or_init(|| {
+            Regex::new(
+                r"^(\d+|[0-9a-fA-F]{8}-([0-9a-fA-F]{4}-){3}[0-9a-fA-F]{12}|[0-9a-fA-F]{24,})$",
+            )
+            .unwrap()
+        });
+        let path = self.path.split('?').next().unwrap_or_default();
+        let path = path
+            .split('/')
+            .map(|segment| {
+                if id.is_match(segment) {
+                    "{id}"
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        format!("{} {}", self.method.to_uppercase(), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_log_skips_blank_lines_and_reports_bad_ones() {
+        let log = "{\"path\": \"/a\"}\n\n{\"method\": \"post\", \"path\": \"/b\"}\n";
+        let entries = load_log(log).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].method, "GET");
+        assert_eq!(entries[1].method, "post");
+
+        let e = load_log("{\"path\": \"/a\"}\n{\"method\": \"get\"}").unwrap_err();
+        assert_eq!(e.to_string(), "line 2");
+    }
+
+    #[test]
+    fn to_request_merges_the_query_and_keeps_the_base_path() {
+        let entry: LogEntry = serde_json::from_value(json!({
+            "method": "put",
+            "path": "/todos/1?a=1&b=2",
+            "query": "b=3",
+            "body": "x=1&y=2",
+            "headers": {"x-token": "t"},
+        }))
+        .unwrap();
+        let base = Url::parse("https://staging.test/v2/").unwrap();
+        let request = entry.to_request(&base).unwrap();
+
+        assert_eq!(request.method, Method::PUT);
+        assert_eq!(request.url.as_str(), "https://staging.test/v2/todos/1");
+        assert_eq!(request.params, Some(json!({"a": "1", "b": "3"})));
+        assert_eq!(request.body, Some(json!({"x": "1", "y": "2"})));
+        assert_eq!(request.headers["x-token"], "t");
+        assert_eq!(
+            request.headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+
+        let entry: LogEntry = serde_json::from_value(json!({
+            "path": "/todos",
+            "headers": {"content-type": "application/json; charset=utf-8"},
+        }))
+        .unwrap();
+        let request = entry.to_request(&base).unwrap();
+        assert!(request.headers.get("content-type").is_none());
+
+        let bad: LogEntry = serde_json::from_value(json!({"path": "/", "query": 1})).unwrap();
+        assert!(bad.to_request(&base).is_err());
+    }
+
+    #[test]
+    fn endpoint_replaces_ids() {
+        let entry: LogEntry = serde_json::from_value(json!({
+            "method": "delete",
+            "path": "/users/42/posts/123e4567-e89b-12d3-a456-426614174000/v2?x=1",
+        }))
+        .unwrap();
+        assert_eq!(entry.endpoint(), "DELETE /users/{id}/posts/{id}/v2");
+    }
+}
@@ -0,0 +1,149 @@
+/// Converters from other request formats into profiles.
+pub mod curl;
+pub mod har;
+pub mod log;
+pub mod openapi;
+pub mod postman;
+
+pub use curl::parse_curl;
+pub use har::{load_har, HarEntry};
+pub use log::{load_log, LogEntry};
+pub use openapi::OpenApi;
+pub use postman::PostmanCollection;
+
+use anyhow::{bail, Result};
+use reqwest::{header::HeaderMap, header::HeaderValue, Method};
+use serde_json::{json, Value};
+use url::Url;
+
+/// A profile name derived from the method and path, e.g. `get_todos_1`. `taken` names get a
+/// numeric suffix.
+pub fn profile_name<'a>(
+    method: &Method,
+    url: &Url,
+    taken: impl IntoIterator<Item = &'a String>,
+) -> String {
+    let mut name = method.as_str().to_lowercase();
+    for segment in url.path_segments().into_iter().flatten() {
+        let segment = segment
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect::<String>();
+        if !segment.is_empty() {
+            name.push('_');
+            name.push_str(&segment.to_lowercase());
+        }
+    }
+
+    let taken = taken.into_iter().collect::<Vec<_>>();
+    let mut candidate = name.clone();
+    let mut i = 2;
+    while taken.contains(&&candidate) {
+        candidate = format!("{}_{}", name, i);
+        i += 1;
+    }
+
+    candidate
+}
+
+/// Move `url` to the environment at `base`, keeping its path and query. The path of `base`
+/// is kept as a prefix, so `https://example.com/todos` moved to `https://staging.com/v2`
+/// becomes `https://staging.com/v2/todos`.
+pub fn swap_origin(url: &Url, base: &Url) -> Result<Url> {
+    let mut swapped = Url::parse(&format!(
+        "{}/{}",
+        base.as_str().trim_end_matches('/'),
+        url.path().trim_start_matches('/')
+    ))?;
+    swapped.set_query(url.query());
+
+    Ok(swapped)
+}
+
+/// Turn the request data into a body object, based on the content type curl would send.
+pub(crate) fn parse_body(data: &str, headers: &mut HeaderMap) -> Result<Value> {
+    let content_type = headers
+        .get("content-type")
+        .map(|v| v.to_str().unwrap_or_default().to_string());
+
+    match content_type.as_deref() {
+        Some(v) if v.starts_with("application/json") => {
+            headers.insert("content-type", HeaderValue::from_static("application/json"));
+            let body: Value = serde_json::from_str(data)?;
+            if !body.is_object() {
+                bail!("Body must be a json object: {}", data);
+            }
+            Ok(body)
+        }
+        None => parse_body_guess(data, headers),
+        Some(v) if v.starts_with("application/x-www-form-urlencoded") => {
+            parse_body_guess(data, headers)
+        }
+        Some(v) => bail!("Unsupported content type: {}", v),
+    }
+}
+
+/// Without a content type curl sends the data url encoded, but browsers often omit it for
+/// json payloads too.
+fn parse_body_guess(data: &str, headers: &mut HeaderMap) -> Result<Value> {
+    if let Ok(body @ Value::Object(_)) = serde_json::from_str(data) {
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        return Ok(body);
+    }
+
+    headers.insert(
+        "content-type",
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    let mut body = json!({});
+    for (key, value) in url::form_urlencoded::parse(data.as_bytes()) {
+        body[&*key] = json!(value);
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profile_name_avoids_taken_names() {
+        let url = Url::parse("https://example.com/todos/1?x=y").unwrap();
+        let taken = ["get_todos_1".to_string(), "get_todos_1_2".to_string()];
+
+        assert_eq!(profile_name(&Method::GET, &url, []), "get_todos_1");
+        assert_eq!(profile_name(&Method::GET, &url, &taken), "get_todos_1_3");
+    }
+
+    #[test]
+    fn swap_origin_keeps_the_base_path() {
+        let url = Url::parse("https://example.com/todos/1?a=1").unwrap();
+        let swap = |base: &str| swap_origin(&url, &Url::parse(base).unwrap()).unwrap();
+
+        assert_eq!(
+            swap("http://localhost:8080").as_str(),
+            "http://localhost:8080/todos/1?a=1"
+        );
+        assert_eq!(
+            swap("https://staging.com/v2/").as_str(),
+            "https://staging.com/v2/todos/1?a=1"
+        );
+    }
+
+    #[test]
+    fn parse_body_guesses_json_or_form() {
+        let mut headers = HeaderMap::new();
+        let body = parse_body(r#"{"a": 1}"#, &mut headers).unwrap();
+        assert_eq!(body, json!({"a": 1}));
+        assert_eq!(headers["content-type"], "application/json");
+
+        let mut headers = HeaderMap::new();
+        let body = parse_body("a=1&b=x%20y", &mut headers).unwrap();
+        assert_eq!(body, json!({"a": "1", "b": "x y"}));
+        assert_eq!(headers["content-type"], "application/x-www-form-urlencoded");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("text/xml"));
+        assert!(parse_body("<a/>", &mut headers).is_err());
+    }
+}
@@ -0,0 +1,530 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde_json::json;
+use serde_yaml::Value;
+use url::Url;
+
+use super::profile_name;
+use crate::RequestProfile;
+
+const METHODS: &[&str] = &[
+    "get", "put", "post", "delete", "options", "head", "patch", "trace",
+];
+
+/// How many references are followed before giving up, e.g. when generating examples.
+const MAX_DEPTH: usize = 8;
+
+/// Characters escaped in a path segment, as in the url standard, plus `/` and `%`.
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}')
+    .add(b'/')
+    .add(b'%');
+
+/// An OpenAPI 3 document, in yaml or json.
+#[derive(Debug)]
+pub struct OpenApi {
+    doc: Value,
+    /// where relative server urls are resolved against
+    base: Url,
+}
+
+impl FromStr for OpenApi {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let doc: Value = serde_yaml::from_str(s)?;
+        match doc.get("openapi").and_then(Value::as_str) {
+            Some(v) if v.starts_with('3') => Ok(Self {
+                doc,
+                base: Url::parse("http://localhost")?,
+            }),
+            _ => bail!("Only OpenAPI 3 documents are supported"),
+        }
+    }
+}
+
+impl OpenApi {
+    /// Resolve relative server urls, like `/api`, against `base` (the url the document is
+    /// served from) instead of `http://localhost`.
+    pub fn with_base(self, base: Url) -> Self {
+        Self { base, ..self }
+    }
+
+    /// The server urls declared by the document, with their variables set to the defaults.
+    pub fn servers(&self) -> Vec<String> {
+        let servers = self.doc.get("servers").and_then(Value::as_sequence);
+        servers
+            .into_iter()
+            .flatten()
+            .filter_map(|server| {
+                let mut url = server.get("url")?.as_str()?.to_string();
+                let variables = server.get("variables").and_then(Value::as_mapping);
+                for (name, var) in variables.into_iter().flatten() {
+                    let default = var.get("default").map(scalar_to_string).unwrap_or_default();
+                    url = url.replace(&format!("{{{}}}", scalar_to_string(name)), &default);
+                }
+                Some(url)
+            })
+            .collect()
+    }
+
+    /// One request per operation, named by its operation id, against `server`. A relative
+    /// `server` is resolved against the base url. Operations which can not be converted are
+    /// returned as errors, named by their operation id or method and path.
+    pub fn requests(&self, server: &str) -> Result<Vec<(String, Result<RequestProfile>)>> {
+        let paths = self
+            .doc
+            .get("paths")
+            .and_then(Value::as_mapping)
+            .ok_or_else(|| anyhow!("No paths found in the document"))?;
+
+        let mut requests: Vec<(String, Result<RequestProfile>)> = vec![];
+        for (path, item) in paths {
+            let path = path
+                .as_str()
+                .ok_or_else(|| anyhow!("Invalid path: {:?}", path))?;
+            let item = self.resolve(item)?;
+
+            for method in METHODS {
+                let Some(operation) = item.get(*method) else {
+                    continue;
+                };
+                let taken = requests.iter().map(|(name, _)| name).collect::<Vec<_>>();
+                let id = operation
+                    .get("operationId")
+                    .and_then(Value::as_str)
+                    .filter(|id| !taken.contains(&&id.to_string()));
+                let request = match self.request(server, path, item, operation, method) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let name = match id {
+                            Some(id) => id.to_string(),
+                            None => format!("{} {}", method.to_uppercase(), path),
+                        };
+                        requests.push((name, Err(e)));
+                        continue;
+                    }
+                };
+
+                // name by the path template rather than the server and parameter values
+                let name = match id {
+                    Some(id) => id.to_string(),
+                    None => {
+                        let template = path.replace(['{', '}'], "");
+                        let template = Url::parse(&format!("http://localhost{}", template))?;
+                        profile_name(&request.method, &template, taken)
+                    }
+                };
+                requests.push((name, Ok(request)));
+            }
+        }
+
+        Ok(requests)
+    }
+
+    fn request(
+        &self,
+        server: &str,
+        path: &str,
+        item: &Value,
+        operation: &Value,
+        method: &str,
+    ) -> Result<RequestProfile> {
+        let mut path = path.to_string();
+        let mut params = json!({});
+        let mut headers = HeaderMap::new();
+
+        // operation parameters override the ones shared by the path
+        let mut parameters: Vec<&Value> = vec![];
+        for p in [item, operation]
+            .iter()
+            .filter_map(|v| v.get("parameters").and_then(Value::as_sequence))
+            .flatten()
+        {
+            let p = self.resolve(p)?;
+            parameters.retain(|v| v.get("name") != p.get("name") || v.get("in") != p.get("in"));
+            parameters.push(p);
+        }
+
+        for p in parameters {
+            let name = p.get("name").and_then(Value::as_str).unwrap_or_default();
+            let required = p.get("required").and_then(Value::as_bool).unwrap_or(false);
+            let example = self.parameter_example(p)?;
+
+            match p.get("in").and_then(Value::as_str) {
+                Some("path") => {
+                    let value = example.map(|v| scalar_to_string(&v)).unwrap_or_default();
+                    let value = utf8_percent_encode(&value, PATH_SEGMENT).to_string();
+                    path = path.replace(&format!("{{{}}}", name), &value);
+                }
+                Some("query") if required || example.is_some() => {
+                    let value = example.map(|v| scalar_to_string(&v)).unwrap_or_default();
+                    params[name] = json!(value);
+                }
+                Some("header") if required || example.is_some() => {
+                    let value = example.map(|v| scalar_to_string(&v)).unwrap_or_default();
+                    headers.insert(HeaderName::from_str(name)?, HeaderValue::from_str(&value)?);
+                }
+                _ => {}
+            }
+        }
+
+        let mut body = None;
+        if let Some(request_body) = operation.get("requestBody") {
+            let content = self.resolve(request_body)?.get("content");
+            for content_type in ["application/json", "application/x-www-form-urlencoded"] {
+                let Some(media) = content.and_then(|c| c.get(content_type)) else {
+                    continue;
+                };
+                let example = self.media_example(media)?;
+                if example.is_mapping() {
+                    headers.insert("content-type", HeaderValue::from_static(content_type));
+                    body = Some(serde_json::to_value(example)?);
+                }
+                break;
+            }
+        }
+
+        let server = match Url::parse(server) {
+            Err(url::ParseError::RelativeUrlWithoutBase) => self.base.join(server)?,
+            server => server?,
+        };
+        let url = Url::parse(&format!(
+            "{}{}",
+            server.as_str().trim_end_matches('/'),
+            path
+        ))?;
+        let method = Method::from_str(&method.to_uppercase())?;
+
+        Ok(RequestProfile::new(
+            url,
+            method,
+            Some(params),
+            body,
+            headers,
+        ))
+    }
+
+    fn parameter_example(&self, p: &Value) -> Result<Option<Value>> {
+        if let Some(example) = p.get("example") {
+            return Ok(Some(example.clone()));
+        }
+        if let Some(example) = first_example(p) {
+            return Ok(Some(
+                self.resolve(example)?
+                    .get("value")
+                    .cloned()
+                    .unwrap_or_default(),
+            ));
+        }
+
+        match p.get("schema") {
+            Some(schema) => {
+                let schema = self.resolve(schema)?;
+                let declared = ["example", "default", "enum"]
+                    .iter()
+                    .any(|k| schema.get(*k).is_some());
+                let required = p.get("required").and_then(Value::as_bool).unwrap_or(false);
+                if declared || required {
+                    Ok(Some(self.example(schema)?))
+                } else {
+                    Ok(None)
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn media_example(&self, media: &Value) -> Result<Value> {
+        if let Some(example) = media.get("example") {
+            return Ok(example.clone());
+        }
+        if let Some(example) = first_example(media) {
+            return Ok(self
+                .resolve(example)?
+                .get("value")
+                .cloned()
+                .unwrap_or_default());
+        }
+        match media.get("schema") {
+            Some(schema) => self.example(schema),
+            None => Ok(Value::Null),
+        }
+    }
+
+    /// Build an example value for `schema`, preferring the examples and defaults it declares.
+    pub fn example(&self, schema: &Value) -> Result<Value> {
+        Ok(self.example_inner(schema, &[])?.unwrap_or_default())
+    }
+
+    /// `refs` are the references being expanded. Recursive ones yield `None`, and the properties
+    /// holding them are left out.
+    fn example_inner(&self, schema: &Value, refs: &[&str]) -> Result<Option<Value>> {
+        let mut refs = refs.to_vec();
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            if refs.contains(&reference) || refs.len() > MAX_DEPTH {
+                return Ok(None);
+            }
+            refs.push(reference);
+        }
+        let schema = self.resolve(schema)?;
+
+        for key in ["example", "default"] {
+            if let Some(v) = schema.get(key) {
+                return Ok(Some(v.clone()));
+            }
+        }
+        if let Some(v) = schema.get("enum").and_then(|v| v.get(0)) {
+            return Ok(Some(v.clone()));
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(Value::as_sequence) {
+            let mut merged = serde_yaml::Mapping::new();
+            for s in all {
+                if let Some(Value::Mapping(m)) = self.example_inner(s, &refs)? {
+                    merged.extend(m);
+                }
+            }
+            return Ok(Some(Value::Mapping(merged)));
+        }
+        for key in ["oneOf", "anyOf"] {
+            if let Some(s) = schema.get(key).and_then(|v| v.get(0)) {
+                return self.example_inner(s, &refs);
+            }
+        }
+
+        let schema_type = match schema.get("type") {
+            Some(Value::Sequence(types)) => types.first().and_then(Value::as_str),
+            Some(v) => v.as_str(),
+            None if schema.get("properties").is_some() => Some("object"),
+            None => None,
+        };
+
+        Ok(Some(match schema_type {
+            Some("object") => {
+                let mut map = serde_yaml::Mapping::new();
+                let properties = schema.get("properties").and_then(Value::as_mapping);
+                for (name, prop) in properties.into_iter().flatten() {
+                    if let Some(v) = self.example_inner(prop, &refs)? {
+                        map.insert(name.clone(), v);
+                    }
+                }
+                Value::Mapping(map)
+            }
+            Some("array") => match schema.get("items") {
+                Some(items) => {
+                    Value::Sequence(self.example_inner(items, &refs)?.into_iter().collect())
+                }
+                None => Value::Sequence(vec![]),
+            },
+            Some("integer") | Some("number") => Value::from(0),
+            Some("boolean") => Value::Bool(false),
+            Some("string") => Value::String(
+                match schema.get("format").and_then(Value::as_str) {
+                    Some("date-time") => "2024-01-01T00:00:00Z",
+                    Some("date") => "2024-01-01",
+                    Some("email") => "user@example.com",
+                    Some("uuid") => "00000000-0000-0000-0000-000000000000",
+                    Some("uri") => "https://example.com",
+                    _ => "string",
+                }
+                .to_string(),
+            ),
+            _ => Value::Null,
+        }))
+    }
+
+    /// Follow a local `$ref` such as `#/components/schemas/Todo`.
+    pub fn resolve<'a>(&'a self, value: &'a Value) -> Result<&'a Value> {
+        let mut value = value;
+        // refs may point at other refs, but not forever
+        for _ in 0..MAX_DEPTH {
+            let Some(reference) = value.get("$ref").and_then(Value::as_str) else {
+                return Ok(value);
+            };
+            let pointer = reference
+                .strip_prefix('#')
+                .ok_or_else(|| anyhow!("Only local references are supported: {}", reference))?;
+
+            value = &self.doc;
+            for segment in pointer.split('/').filter(|s| !s.is_empty()) {
+                let segment = segment.replace("~1", "/").replace("~0", "~");
+                value = value
+                    .get(segment.as_str())
+                    .ok_or_else(|| anyhow!("Unresolved reference: {}", reference))?;
+            }
+        }
+
+        bail!("Too many nested references")
+    }
+}
+
+fn first_example(value: &Value) -> Option<&Value> {
+    value
+        .get("examples")
+        .and_then(Value::as_mapping)
+        .and_then(|m| m.values().next())
+}
+
+fn scalar_to_string(value: &Value) -> String {
+    match value {
+        Value::String(v) => v.clone(),
+        Value::Bool(v) => v.to_string(),
+        Value::Number(v) => v.to_string(),
+        Value::Null => String::new(),
+        v => serde_yaml::to_string(v)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SPEC: &str = r##"
+openapi: 3.0.0
+servers:
+  - url: /api
+  - url: https://{env}.example.com/v1
+    variables:
+      env:
+        default: staging
+paths:
+  /todos/{id}:
+    parameters:
+      - name: id
+        in: path
+        required: true
+        schema:
+          type: string
+          example: a b/c
+    get:
+      operationId: getTodo
+      parameters:
+        - name: verbose
+          in: query
+          schema:
+            type: boolean
+            default: true
+        - name: page
+          in: query
+          schema:
+            type: integer
+    put:
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Todo"
+components:
+  schemas:
+    Todo:
+      type: object
+      properties:
+        title:
+          type: string
+        done:
+          type: boolean
+        due:
+          type: string
+          format: date
+        parent:
+          $ref: "#/components/schemas/Todo"
+"##;
+
+    #[test]
+    fn servers_fill_in_variables() {
+        let spec: OpenApi = SPEC.parse().unwrap();
+        assert_eq!(spec.servers(), ["/api", "https://staging.example.com/v1"]);
+    }
+
+    #[test]
+    fn requests_from_operations() {
+        let spec: OpenApi = SPEC.parse().unwrap();
+        let requests = spec.requests("https://staging.example.com/v1").unwrap();
+        let names = requests
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["getTodo", "put_todos_id"]);
+
+        let get = requests[0].1.as_ref().unwrap();
+        assert_eq!(get.method, Method::GET);
+        assert_eq!(
+            get.url.as_str(),
+            "https://staging.example.com/v1/todos/a%20b%2Fc"
+        );
+        assert_eq!(get.params, Some(json!({"verbose": "true"})));
+
+        let put = requests[1].1.as_ref().unwrap();
+        assert_eq!(put.method, Method::PUT);
+        assert_eq!(
+            put.body,
+            Some(json!({"title": "string", "done": false, "due": "2024-01-01"}))
+        );
+        assert_eq!(put.headers["content-type"], "application/json");
+    }
+
+    #[test]
+    fn relative_servers_resolve_against_the_base() {
+        let spec: OpenApi = SPEC.parse().unwrap();
+        let requests = spec.requests("/api").unwrap();
+        assert_eq!(
+            requests[0].1.as_ref().unwrap().url.as_str(),
+            "http://localhost/api/todos/a%20b%2Fc"
+        );
+
+        let spec = spec.with_base(Url::parse("https://example.com/docs/openapi.yml").unwrap());
+        let requests = spec.requests("/api").unwrap();
+        assert_eq!(
+            requests[0].1.as_ref().unwrap().url.as_str(),
+            "https://example.com/api/todos/a%20b%2Fc"
+        );
+    }
+
+    #[test]
+    fn operations_which_fail_are_returned_as_errors() {
+        let spec: OpenApi = r##"
+openapi: 3.0.0
+paths:
+  /todos:
+    get:
+      operationId: listTodos
+    post:
+      requestBody:
+        content:
+          application/json:
+            schema:
+              $ref: "#/components/schemas/Missing"
+"##
+        .parse()
+        .unwrap();
+        let requests = spec.requests("https://example.com").unwrap();
+
+        assert_eq!(requests[0].0, "listTodos");
+        assert!(requests[0].1.is_ok());
+        assert_eq!(requests[1].0, "POST /todos");
+        assert!(requests[1].1.is_err());
+    }
+
+    #[test]
+    fn only_openapi_3_is_supported() {
+        assert!("swagger: '2.0'".parse::<OpenApi>().is_err());
+    }
+}
@@ -0,0 +1,493 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use url::Url;
+
+use crate::{template::Vars, ReqProfile, RequestProfile};
+
+/// A Postman collection (format v2.1).
+#[derive(Debug, Deserialize)]
+pub struct PostmanCollection {
+    #[serde(default)]
+    item: Vec<Item>,
+    #[serde(default)]
+    variable: Vec<KeyValue>,
+    #[serde(default)]
+    auth: Option<Auth>,
+}
+
+/// A request, or a folder of further items.
+#[derive(Debug, Deserialize)]
+struct Item {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    item: Vec<Item>,
+    #[serde(default)]
+    request: Option<Request>,
+    #[serde(default)]
+    auth: Option<Auth>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    header: Vec<KeyValue>,
+    url: PostmanUrl,
+    #[serde(default)]
+    body: Option<Body>,
+    #[serde(default)]
+    auth: Option<Auth>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum PostmanUrl {
+    Raw(String),
+    Object {
+        raw: String,
+        #[serde(default)]
+        query: Vec<KeyValue>,
+        #[serde(default)]
+        variable: Vec<KeyValue>,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+struct Body {
+    mode: String,
+    #[serde(default)]
+    raw: String,
+    #[serde(default)]
+    urlencoded: Vec<KeyValue>,
+    #[serde(default)]
+    formdata: Vec<KeyValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Auth {
+    #[serde(rename = "type")]
+    auth_type: String,
+    #[serde(default)]
+    bearer: Vec<KeyValue>,
+    #[serde(default)]
+    basic: Vec<KeyValue>,
+    #[serde(default)]
+    apikey: Vec<KeyValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct KeyValue {
+    key: String,
+    #[serde(default)]
+    value: Value,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+}
+
+impl FromStr for PostmanCollection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(s)?)
+    }
+}
+
+impl PostmanCollection {
+    /// Convert every request into a profile named after its folders and name. Collection
+    /// variables, together with `vars`, become the profile's `vars`, except in urls where they
+    /// are filled in so the url can be parsed. Requests that can not be converted are returned
+    /// as errors.
+    pub fn profiles(&self, vars: &Vars) -> Vec<(String, Result<ReqProfile>)> {
+        let mut all_vars = self
+            .variable
+            .iter()
+            .filter(|v| !v.disabled)
+            .map(|v| (v.key.clone(), v.value.clone()))
+            .collect::<Vars>();
+        all_vars.extend(vars.clone());
+
+        let mut profiles = vec![];
+        collect(&self.item, "", self.auth.as_ref(), &all_vars, &mut profiles);
+        profiles
+    }
+}
+
+fn collect(
+    items: &[Item],
+    prefix: &str,
+    auth: Option<&Auth>,
+    vars: &Vars,
+    profiles: &mut Vec<(String, Result<ReqProfile>)>,
+) {
+    for item in items {
+        let name = match prefix {
+            "" => snake_case(&item.name),
+            _ => format!("{}_{}", prefix, snake_case(&item.name)),
+        };
+        let auth = own_auth(item.auth.as_ref()).or(auth);
+
+        match &item.request {
+            Some(request) => {
+                let mut unique = name.clone();
+                let mut i = 2;
+                while profiles.iter().any(|(n, _)| n == &unique) {
+                    unique = format!("{}_{}", name, i);
+                    i += 1;
+                }
+                let profile = request.to_profile(own_auth(request.auth.as_ref()).or(auth), vars);
+                profiles.push((unique, profile));
+            }
+            None => collect(&item.item, &name, auth, vars, profiles),
+        }
+    }
+}
+
+/// The auth an item sets itself, `None` when it inherits the auth of its folder or collection.
+fn own_auth(auth: Option<&Auth>) -> Option<&Auth> {
+    auth.filter(|auth| auth.auth_type != "inherit")
+}
+
+impl Request {
+    fn to_profile(&self, auth: Option<&Auth>, vars: &Vars) -> Result<ReqProfile> {
+        let (raw, query, path_vars) = match &self.url {
+            PostmanUrl::Raw(raw) => (raw, None, &[][..]),
+            PostmanUrl::Object {
+                raw,
+                query,
+                variable,
+            } => (raw, Some(query), &variable[..]),
+        };
+
+        // the url has to parse, so variables are filled in, except `:id` path variables which
+        // become placeholders
+        let mut raw = fill_placeholders(raw, vars);
+        if raw.contains("{{") {
+            bail!("undefined variable in url {}", raw);
+        }
+        let mut vars = vars.clone();
+        for v in path_vars {
+            // whole segments only, so `:id` leaves `:identifier` alone
+            let segment = Regex::new(&format!("/:{}([/?#]|$)", regex::escape(&v.key)))?;
+            raw = segment
+                .replace_all(&raw, format!("/{{{{{}}}}}$1", v.key))
+                .to_string();
+            vars.insert(v.key.clone(), v.value.clone());
+        }
+
+        let mut url = Url::parse(&raw).map_err(|e| anyhow!("invalid url {}: {}", raw, e))?;
+        let mut params = json!({});
+        match query {
+            Some(query) => {
+                for q in query.iter().filter(|q| !q.disabled) {
+                    params[&q.key] = json!(value_to_string(&q.value));
+                }
+            }
+            None => {
+                for (key, value) in url.query_pairs() {
+                    params[&*key] = json!(value);
+                }
+            }
+        }
+        url.set_query(None);
+
+        let mut headers = HeaderMap::new();
+        for h in self.header.iter().filter(|h| !h.disabled) {
+            headers.append(
+                HeaderName::from_str(&h.key)?,
+                HeaderValue::from_str(&value_to_string(&h.value))?,
+            );
+        }
+
+        if let Some(auth) = auth {
+            apply_auth(auth, &mut headers, &mut params, &vars)?;
+        }
+
+        // the body sets the content type it is sent with, a request without one needs none
+        let body = match &self.body {
+            Some(body) => body.to_json(&mut headers)?,
+            None => None,
+        };
+        if body.is_none() {
+            headers.remove("content-type");
+        }
+
+        let method = self.method.as_deref().unwrap_or("GET").to_uppercase();
+        let request =
+            RequestProfile::new(url, Method::from_str(&method)?, Some(params), body, headers);
+
+        let mut profile = ReqProfile::new(request, vec![]);
+        profile.vars = used_vars(&profile, &vars)?;
+        Ok(profile)
+    }
+}
+
+impl Body {
+    fn to_json(&self, headers: &mut HeaderMap) -> Result<Option<Value>> {
+        let fields = match self.mode.as_str() {
+            "raw" if self.raw.trim().is_empty() => return Ok(None),
+            "raw" => {
+                headers.insert("content-type", HeaderValue::from_static("application/json"));
+                let body: Value = serde_json::from_str(&quote_placeholders(&self.raw)?)
+                    .map_err(|_| anyhow!("only json raw bodies are supported"))?;
+                if !body.is_object() {
+                    bail!("raw body must be a json object");
+                }
+                return Ok(Some(body));
+            }
+            "urlencoded" => &self.urlencoded,
+            "formdata" => &self.formdata,
+            mode => bail!("unsupported body mode: {}", mode),
+        };
+
+        headers.insert(
+            "content-type",
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
+        let mut body = json!({});
+        for field in fields.iter().filter(|f| !f.disabled) {
+            if field.kind.as_deref() == Some("file") {
+                bail!("uploading files is not supported");
+            }
+            body[&field.key] = json!(value_to_string(&field.value));
+        }
+        Ok(Some(body))
+    }
+}
+
+fn apply_auth(auth: &Auth, headers: &mut HeaderMap, params: &mut Value, vars: &Vars) -> Result<()> {
+    let get = |list: &[KeyValue], key: &str| {
+        list.iter()
+            .find(|v| v.key == key)
+            .map(|v| value_to_string(&v.value))
+            .unwrap_or_default()
+    };
+
+    match auth.auth_type.as_str() {
+        "bearer" => {
+            let token = get(&auth.bearer, "token");
+            headers.insert(
+                "authorization",
+                HeaderValue::from_str(&format!("Bearer {}", token))?,
+            );
+        }
+        "basic" => {
+            // the credentials are encoded, so placeholders have to be filled in now
+            let credentials = format!(
+                "{}:{}",
+                get(&auth.basic, "username"),
+                get(&auth.basic, "password")
+            );
+            let credentials = fill_placeholders(&credentials, vars);
+            if credentials.contains("{{") {
+                bail!("undefined variable in basic auth credentials");
+            }
+            headers.insert(
+                "authorization",
+                HeaderValue::from_str(&format!("Basic {}", STANDARD.encode(credentials)))?,
+            );
+        }
+        "apikey" => {
+            let key = get(&auth.apikey, "key");
+            let value = get(&auth.apikey, "value");
+            if get(&auth.apikey, "in") == "query" {
+                params[key] = json!(value);
+            } else {
+                headers.insert(HeaderName::from_str(&key)?, HeaderValue::from_str(&value)?);
+            }
+        }
+        "noauth" => {}
+        other => bail!("unsupported auth type: {}", other),
+    }
+
+    Ok(())
+}
+
+/// Replace the placeholders which have a value, leaving the others untouched.
+fn fill_placeholders(s: &str, vars: &Vars) -> String {
+    let mut s = s.to_string();
+    for (key, value) in vars {
+        s = s.replace(&format!("{{{{{}}}}}", key), &value_to_string(value));
+    }
+    s
+}
+
+/// Postman allows placeholders as bare json values, e.g. `{"id": {{id}}}`. Quote them so the
+/// body parses; a string holding just a placeholder is rendered with the variable's type.
+fn quote_placeholders(raw: &str) -> Result<String> {
+    let re = Regex::new(r#"("?)\{\{\s*([^{}\s]+)\s*\}\}("?)"#)?;
+    Ok(re
+        .replace_all(raw, |caps: &regex::Captures| {
+            if caps[1].is_empty() && caps[3].is_empty() {
+                format!("\"{{{{{}}}}}\"", &caps[2])
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string())
+}
+
+/// The variables the profile refers to.
+fn used_vars(profile: &ReqProfile, vars: &Vars) -> Result<Vars> {
+    let text = serde_yaml::to_string(&profile.request)?
+        .replace("%7B%7B", "{{")
+        .replace("%7D%7D", "}}");
+    Ok(vars
+        .iter()
+        .filter(|(key, _)| text.contains(&format!("{{{{{}}}}}", key)))
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect())
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(v) => v.clone(),
+        Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+fn snake_case(s: &str) -> String {
+    s.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLLECTION: &str = r#"{
+        "variable": [{"key": "base", "value": "https://example.com"}, {"key": "token", "value": "t"}],
+        "auth": {"type": "bearer", "bearer": [{"key": "token", "value": "{{token}}"}]},
+        "item": [
+            {"name": "Users", "item": [
+                {"name": "Get User", "request": {
+                    "url": {
+                        "raw": "{{base}}/users/:id/:identifier?full=1",
+                        "query": [{"key": "full", "value": "1"}, {"key": "x", "value": "2", "disabled": true}],
+                        "variable": [{"key": "id", "value": 7}, {"key": "identifier", "value": "abc"}]
+                    }
+                }},
+                {"name": "Create User", "request": {
+                    "method": "POST",
+                    "url": "{{base}}/users",
+                    "auth": {"type": "basic", "basic": [
+                        {"key": "username", "value": "u"}, {"key": "password", "value": "{{token}}"}
+                    ]},
+                    "body": {"mode": "raw", "raw": "{\"id\": {{token}}, \"name\": \"{{token}}-x\"}"}
+                }}
+            ]},
+            {"name": "Upload", "request": {
+                "method": "POST",
+                "url": "{{base}}/upload",
+                "body": {"mode": "formdata", "formdata": [{"key": "f", "type": "file"}]}
+            }},
+            {"name": "Missing", "request": {"url": "{{host}}/x"}}
+        ]
+    }"#;
+
+    fn profiles() -> Vec<(String, Result<ReqProfile>)> {
+        let collection: PostmanCollection = COLLECTION.parse().unwrap();
+        collection.profiles(&Vars::new())
+    }
+
+    #[test]
+    fn folders_become_prefixes() {
+        let names = profiles().into_iter().map(|(n, _)| n).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["users_get_user", "users_create_user", "upload", "missing"]
+        );
+    }
+
+    #[test]
+    fn path_variables_become_placeholders() {
+        let (_, profile) = profiles().remove(0);
+        let profile = profile.unwrap();
+
+        assert_eq!(
+            profile.request.url.as_str(),
+            "https://example.com/users/%7B%7Bid%7D%7D/%7B%7Bidentifier%7D%7D"
+        );
+        assert_eq!(profile.request.params, Some(json!({"full": "1"})));
+        assert_eq!(profile.request.headers["authorization"], "Bearer {{token}}");
+        assert_eq!(profile.vars["id"], json!(7));
+        assert_eq!(profile.vars["identifier"], json!("abc"));
+        assert_eq!(profile.vars["token"], json!("t"));
+        assert!(!profile.vars.contains_key("base"));
+
+        let rendered = profile.request.render(&profile.vars).unwrap();
+        assert_eq!(rendered.url.as_str(), "https://example.com/users/7/abc");
+    }
+
+    #[test]
+    fn raw_bodies_and_basic_auth() {
+        let (_, profile) = profiles().remove(1);
+        let profile = profile.unwrap();
+
+        assert_eq!(profile.request.method, Method::POST);
+        assert_eq!(
+            profile.request.body,
+            Some(json!({"id": "{{token}}", "name": "{{token}}-x"}))
+        );
+        assert_eq!(profile.request.headers["authorization"], "Basic dTp0");
+    }
+
+    #[test]
+    fn inherited_auth_and_content_types() {
+        let collection: PostmanCollection = r#"{
+            "auth": {"type": "bearer", "bearer": [{"key": "token", "value": "c"}]},
+            "item": [
+                {"name": "Folder", "auth": {"type": "inherit"}, "item": [
+                    {"name": "Get", "request": {
+                        "auth": {"type": "inherit"},
+                        "url": "https://example.com/a",
+                        "header": [{"key": "Content-Type", "value": "application/json; charset=utf-8"}]
+                    }},
+                    {"name": "Post", "request": {
+                        "method": "POST",
+                        "url": "https://example.com/a",
+                        "header": [{"key": "Content-Type", "value": "application/json; charset=utf-8"}],
+                        "body": {"mode": "raw", "raw": "{\"a\": 1}"}
+                    }}
+                ]},
+                {"name": "Open", "auth": {"type": "noauth"}, "request": {"url": "https://example.com/b"}}
+            ]
+        }"#
+        .parse()
+        .unwrap();
+        let profiles = collection
+            .profiles(&Vars::new())
+            .into_iter()
+            .map(|(_, p)| p.unwrap().request)
+            .collect::<Vec<_>>();
+
+        assert_eq!(profiles[0].headers["authorization"], "Bearer c");
+        assert!(profiles[0].headers.get("content-type").is_none());
+        assert_eq!(profiles[1].headers["content-type"], "application/json");
+        assert_eq!(profiles[1].headers["authorization"], "Bearer c");
+        assert!(profiles[2].headers.get("authorization").is_none());
+    }
+
+    #[test]
+    fn unsupported_requests_are_errors() {
+        let profiles = profiles();
+        assert!(profiles[2].1.is_err());
+        assert!(profiles[3].1.is_err());
+    }
+}
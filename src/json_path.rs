@@ -0,0 +1,254 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// A single segment of a JSON path such as `$.items[0].id` or `$.items[*].name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+/// Parse a JSON path. The leading `$` is optional, so `data.id` and `$.data.id` are the same.
+pub fn parse(path: &str) -> Result<Vec<Segment>> {
+    let invalid = || anyhow!("Invalid json path: {}", path);
+    let rest = path.trim();
+    let mut rest = rest.strip_prefix('$').unwrap_or(rest);
+    let mut segments = vec![];
+
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            rest = r;
+            continue;
+        }
+
+        if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or_else(invalid)?;
+            let inner = r[..end].trim();
+            let segment = if inner == "*" {
+                Segment::Wildcard
+            } else if let Some(key) = inner
+                .strip_prefix('\'')
+                .and_then(|v| v.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+            {
+                Segment::Key(key.to_string())
+            } else {
+                Segment::Index(inner.parse().map_err(|_| invalid())?)
+            };
+            segments.push(segment);
+            rest = &r[end + 1..];
+            continue;
+        }
+
+        let end = rest.find(['.', '[']).unwrap_or(rest.len());
+        let key = &rest[..end];
+        segments.push(if key == "*" {
+            Segment::Wildcard
+        } else {
+            Segment::Key(key.to_string())
+        });
+        rest = &rest[end..];
+    }
+
+    Ok(segments)
+}
+
+/// Return every value matching `path`.
+pub fn query<'a>(value: &'a Value, path: &str) -> Result<Vec<&'a Value>> {
+    let segments = parse(path)?;
+    let mut current = vec![value];
+
+    for segment in &segments {
+        current = current
+            .into_iter()
+            .flat_map(|v| -> Vec<&Value> {
+                match (segment, v) {
+                    (Segment::Key(key), Value::Object(map)) => map.get(key).into_iter().collect(),
+                    (Segment::Index(idx), Value::Array(arr)) => arr.get(*idx).into_iter().collect(),
+                    (Segment::Wildcard, Value::Array(arr)) => arr.iter().collect(),
+                    (Segment::Wildcard, Value::Object(map)) => map.values().collect(),
+                    _ => vec![],
+                }
+            })
+            .collect();
+    }
+
+    Ok(current)
+}
+
+/// Return the first value matching `path`.
+pub fn get<'a>(value: &'a Value, path: &str) -> Result<Option<&'a Value>> {
+    Ok(query(value, path)?.into_iter().next())
+}
+
+/// Remove every value matching `path`. Removing the root (`$`) leaves `null`.
+pub fn remove(value: &mut Value, path: &str) -> Result<()> {
+    let segments = parse(path)?;
+    match segments.split_last() {
+        Some((last, parents)) => remove_segments(value, parents, last),
+        None => *value = Value::Null,
+    }
+    Ok(())
+}
+
+fn remove_segments(value: &mut Value, parents: &[Segment], last: &Segment) {
+    let Some((first, rest)) = parents.split_first() else {
+        match (last, value) {
+            (Segment::Key(key), Value::Object(map)) => {
+                map.remove(key);
+            }
+            (Segment::Index(idx), Value::Array(arr)) if *idx < arr.len() => {
+                arr.remove(*idx);
+            }
+            (Segment::Wildcard, Value::Array(arr)) => arr.clear(),
+            (Segment::Wildcard, Value::Object(map)) => map.clear(),
+            _ => {}
+        }
+        return;
+    };
+
+    match (first, value) {
+        (Segment::Key(key), Value::Object(map)) => {
+            if let Some(v) = map.get_mut(key) {
+                remove_segments(v, rest, last);
+            }
+        }
+        (Segment::Index(idx), Value::Array(arr)) => {
+            if let Some(v) = arr.get_mut(*idx) {
+                remove_segments(v, rest, last);
+            }
+        }
+        (Segment::Wildcard, Value::Array(arr)) => {
+            for v in arr {
+                remove_segments(v, rest, last);
+            }
+        }
+        (Segment::Wildcard, Value::Object(map)) => {
+            for v in map.values_mut() {
+                remove_segments(v, rest, last);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Return the paths of the values which differ between `a` and `b`, down to the leaves. Array
+/// indexes are written as `[*]`, so a field that differs in several items is listed once. When
+/// the roots themselves differ (e.g. two scalars) the path is `$`.
+pub fn diff_paths(a: &Value, b: &Value) -> Vec<String> {
+    let mut paths = vec![];
+    collect_diff(a, b, "", &mut paths);
+
+    let mut seen = std::collections::HashSet::new();
+    paths.retain(|p| seen.insert(p.clone()));
+    paths
+}
+
+fn collect_diff(a: &Value, b: &Value, path: &str, paths: &mut Vec<String>) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            for key in a.keys().chain(b.keys().filter(|k| !a.contains_key(*k))) {
+                let child = join_key(path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => collect_diff(a, b, &child, paths),
+                    _ => paths.push(child),
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) if a.len() == b.len() => {
+            let child = format!("{}[*]", path);
+            for (a, b) in a.iter().zip(b) {
+                collect_diff(a, b, &child, paths);
+            }
+        }
+        (a, b) if a != b => paths.push(match path {
+            "" => "$".to_string(),
+            _ => path.to_string(),
+        }),
+        _ => {}
+    }
+}
+
+fn join_key(path: &str, key: &str) -> String {
+    let plain = !key.is_empty()
+        && key != "*"
+        && key
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    match (path, plain) {
+        ("", true) => key.to_string(),
+        (_, true) => format!("{}.{}", path, key),
+        _ => format!("{}['{}']", path, key),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_accepts_dots_brackets_and_optional_root() {
+        let expected = vec![
+            Segment::Key("items".to_string()),
+            Segment::Index(0),
+            Segment::Wildcard,
+            Segment::Key("a b".to_string()),
+        ];
+        assert_eq!(parse("$.items[0][*]['a b']").unwrap(), expected);
+        assert_eq!(parse("items[0].*[\"a b\"]").unwrap(), expected);
+        assert!(parse("items[x]").is_err());
+        assert!(parse("items[0").is_err());
+    }
+
+    #[test]
+    fn query_follows_wildcards() {
+        let value = json!({"items": [{"id": 1}, {"id": 2}, {"name": "x"}]});
+        assert_eq!(
+            query(&value, "$.items[*].id").unwrap(),
+            [&json!(1), &json!(2)]
+        );
+        assert_eq!(get(&value, "items[1].id").unwrap(), Some(&json!(2)));
+        assert_eq!(get(&value, "items[5].id").unwrap(), None);
+    }
+
+    #[test]
+    fn remove_follows_wildcards_and_clears_the_root() {
+        let mut value = json!({"items": [{"id": 1, "a": 1}, {"id": 2}], "ts": 3});
+        remove(&mut value, "items[*].id").unwrap();
+        remove(&mut value, "$.ts").unwrap();
+        remove(&mut value, "missing[0].x").unwrap();
+        assert_eq!(value, json!({"items": [{"a": 1}, {}]}));
+
+        remove(&mut value, "$").unwrap();
+        assert_eq!(value, Value::Null);
+    }
+
+    #[test]
+    fn diff_paths_lists_each_leaf_once() {
+        let a =
+            json!({"id": 1, "items": [{"t": 1}, {"t": 2}], "same": true, "a b": 1, "list": [1]});
+        let b = json!({"id": 2, "items": [{"t": 3}, {"t": 4}], "same": true, "new": 0, "list": []});
+        assert_eq!(
+            diff_paths(&a, &b),
+            ["['a b']", "id", "items[*].t", "list", "new"]
+        );
+        assert!(diff_paths(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn diff_paths_marks_a_differing_root() {
+        let (a, b) = (json!(1), json!("x"));
+        assert_eq!(diff_paths(&a, &b), ["$"]);
+        assert_eq!(diff_paths(&json!([1]), &json!([1, 2])), ["$"]);
+
+        // skipping the marker makes the bodies equal
+        let (mut a, mut b) = (a, b);
+        for path in diff_paths(&a, &b) {
+            remove(&mut a, &path).unwrap();
+            remove(&mut b, &path).unwrap();
+        }
+        assert_eq!(a, b);
+    }
+}
@@ -1,11 +1,29 @@
 /// This module contains the main functionality of the `xdiff` library.
+pub mod cassette;
 pub mod cli;
 pub mod config;
+pub mod cookie;
+pub mod diff;
+mod export;
+pub mod import;
+pub mod json_path;
+pub mod load;
+pub mod proxy;
+pub mod replay;
+pub mod schema;
+pub mod snapshot;
+pub mod stats;
+pub mod template;
+#[cfg(test)]
+mod test_server;
 pub mod utils;
 
+use cassette::Cassette;
+
 pub use config::{
-    is_default, DiffConfig, DiffProfile, LoadYaml, ReqConfig, ReqProfile, RequestProfile,
-    ResponseExt, ResponseProfile, ValidateConfig,
+    is_default, AssertionResult, Assertions, DiffConfig, DiffProfile, DiffReport, LoadYaml, Matrix,
+    ReqConfig, ReqProfile, RequestProfile, ResponseExt, ResponseProfile, ValidateConfig,
+    WorkflowStep,
 };
 
 /// Represents additional arguments for the `xdiff` library.
@@ -14,4 +32,22 @@ pub struct ExtraArgs {
     headers: Vec<(String, String)>,
     body: Vec<(String, String)>,
     query: Vec<(String, String)>,
+    cassette: Option<Cassette>,
+}
+
+impl ExtraArgs {
+    /// Record requests to, or replay them from, `cassette`.
+    pub fn with_cassette(mut self, cassette: Option<Cassette>) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    /// The same arguments without the header, body and query overrides, for requests other
+    /// than the one being run (e.g. workflow steps).
+    pub fn without_overrides(&self) -> Self {
+        Self {
+            cassette: self.cassette.clone(),
+            ..Default::default()
+        }
+    }
 }
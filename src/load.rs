@@ -0,0 +1,166 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use tokio::{
+    sync::Mutex,
+    time::{interval, timeout_at, Instant, MissedTickBehavior},
+};
+
+use crate::{ExtraArgs, ReqProfile};
+
+/// How hard to load a profile.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    /// requests in flight at the same time
+    pub concurrency: usize,
+    /// requests per second across all workers, unlimited when `None`
+    pub rate: Option<u32>,
+    pub duration: Duration,
+}
+
+/// Outcome of a load test.
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub elapsed: Duration,
+    /// number of responses per status code
+    pub statuses: BTreeMap<u16, usize>,
+    /// number of failed requests per error message
+    pub errors: BTreeMap<String, usize>,
+    /// latency of every response, in the order they arrived
+    pub latencies: Vec<Duration>,
+}
+
+impl LoadReport {
+    pub fn requests(&self) -> usize {
+        self.latencies.len() + self.errors.values().sum::<usize>()
+    }
+}
+
+impl ReqProfile {
+    /// Send the request of this profile repeatedly for `options.duration`. Steps run once up
+    /// front, so every request uses the same variables.
+    pub async fn load_test(&self, extra: ExtraArgs, options: &LoadOptions) -> Result<LoadReport> {
+        let request = Arc::new(self.resolve(&extra).await?);
+        let client = request.client().await?;
+        let extra = Arc::new(extra);
+        let ticker = options.rate.map(|rate| {
+            let mut ticker = interval(Duration::from_secs_f64(1.0 / rate.max(1) as f64));
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            Arc::new(Mutex::new(ticker))
+        });
+        let report = Arc::new(std::sync::Mutex::new(LoadReport::default()));
+
+        let start = Instant::now();
+        let deadline = start + options.duration;
+        let workers = (0..options.concurrency.max(1))
+            .map(|_| {
+                let (request, client, extra, ticker, report) = (
+                    request.clone(),
+                    client.clone(),
+                    extra.clone(),
+                    ticker.clone(),
+                    report.clone(),
+                );
+                tokio::spawn(async move {
+                    loop {
+                        if let Some(ticker) = &ticker {
+                            let tick = async { ticker.lock().await.tick().await };
+                            if timeout_at(deadline, tick).await.is_err() {
+                                break;
+                            }
+                        }
+                        // requests still in flight at the deadline are dropped, not counted
+                        let send = request.send_with(&client, &extra);
+                        let Ok(result) = timeout_at(deadline, send).await else {
+                            break;
+                        };
+                        let mut report = report.lock().unwrap();
+                        match result {
+                            Ok(res) => {
+                                *report.statuses.entry(res.status().as_u16()).or_default() += 1;
+                                report.latencies.push(res.elapsed());
+                            }
+                            Err(e) => *report.errors.entry(e.to_string()).or_default() += 1,
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for worker in workers {
+            worker.await?;
+        }
+
+        let mut report = std::mem::take(&mut *report.lock().unwrap());
+        report.elapsed = start.elapsed();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use hyper::{Body, Response};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn profile(url: &str) -> ReqProfile {
+        serde_yaml::from_str(&format!("request:\n  url: {}\n", url)).unwrap()
+    }
+
+    fn options(concurrency: usize, rate: Option<u32>, duration_ms: u64) -> LoadOptions {
+        LoadOptions {
+            concurrency,
+            rate,
+            duration: Duration::from_millis(duration_ms),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn load_test_counts_every_response() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let served = count.clone();
+        let addr = serve(move |parts, _| {
+            count.fetch_add(1, Ordering::SeqCst);
+            let status = match parts.uri.path() {
+                "/missing" => 404,
+                _ => 200,
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::empty())
+                .unwrap()
+        });
+
+        let report = profile(&format!("http://{}/", addr))
+            .load_test(ExtraArgs::default(), &options(2, None, 200))
+            .await
+            .unwrap();
+        assert!(report.requests() > 0);
+        assert_eq!(report.statuses.keys().collect::<Vec<_>>(), [&200]);
+        // requests cut off by the deadline reach the server but are not counted
+        assert!(report.requests() <= served.load(Ordering::SeqCst));
+
+        let report = profile(&format!("http://{}/missing", addr))
+            .load_test(ExtraArgs::default(), &options(1, Some(20), 300))
+            .await
+            .unwrap();
+        assert!((1..=8).contains(&report.statuses[&404]));
+        assert!(report.errors.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn load_test_stops_at_the_deadline() {
+        let addr = serve(|_, _| {
+            std::thread::sleep(std::time::Duration::from_secs(2));
+            Response::new(Body::empty())
+        });
+
+        let report = profile(&format!("http://{}/", addr))
+            .load_test(ExtraArgs::default(), &options(2, None, 200))
+            .await
+            .unwrap();
+        assert!(report.elapsed < Duration::from_secs(1));
+        assert_eq!(report.requests(), 0);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    convert::Infallible,
+    io::{stdout, Write},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use anyhow::{anyhow, Result};
+use console::Style;
+use hyper::{
+    body::to_bytes,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use reqwest::{header::HeaderMap, redirect, Client};
+use url::Url;
+
+use crate::{diff::ResponseDiff, utils::response_diff_report, ResponseExt, ResponseProfile};
+
+/// Headers that only apply to a single connection and are not forwarded.
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+/// Forwards every request to a primary and a candidate upstream. Clients get the primary's
+/// response, while the candidate's response is diffed against it in the background.
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    inner: Arc<MirrorInner>,
+}
+
+#[derive(Debug)]
+struct MirrorInner {
+    primary: Url,
+    candidate: Url,
+    response: ResponseProfile,
+    client: Client,
+    requests: AtomicUsize,
+    differences: AtomicUsize,
+}
+
+impl Mirror {
+    pub fn new(primary: Url, candidate: Url, response: ResponseProfile) -> Result<Self> {
+        // redirects are passed on to the client, which decides whether to follow them
+        let client = Client::builder()
+            .redirect(redirect::Policy::none())
+            .build()?;
+
+        Ok(Self {
+            inner: Arc::new(MirrorInner {
+                primary,
+                candidate,
+                response,
+                client,
+                requests: AtomicUsize::new(0),
+                differences: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    /// Number of requests whose candidate response has been compared so far.
+    pub fn requests(&self) -> usize {
+        self.inner.requests.load(Ordering::SeqCst)
+    }
+
+    /// Number of compared requests whose responses differ.
+    pub fn differences(&self) -> usize {
+        self.inner.differences.load(Ordering::SeqCst)
+    }
+
+    /// Accept requests on `addr` until the process is stopped.
+    pub async fn serve(self, addr: SocketAddr) -> Result<()> {
+        let make_svc = make_service_fn(move |_| {
+            let mirror = self.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let mirror = mirror.clone();
+                    async move { Ok::<_, Infallible>(mirror.handle(req).await) }
+                }))
+            }
+        });
+
+        Server::try_bind(&addr)?.serve(make_svc).await?;
+
+        Ok(())
+    }
+
+    async fn handle(&self, req: Request<Body>) -> Response<Body> {
+        match self.forward(req).await {
+            Ok(res) => res,
+            Err(e) => Response::builder()
+                .status(502)
+                .body(Body::from(format!("xdiff proxy: {}", e)))
+                .unwrap_or_default(),
+        }
+    }
+
+    async fn forward(&self, req: Request<Body>) -> Result<Response<Body>> {
+        let (parts, body) = req.into_parts();
+        let body = to_bytes(body).await?;
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|v| v.as_str())
+            .unwrap_or("/");
+        let label = format!("{} {}", parts.method, path);
+
+        let mut headers = HeaderMap::new();
+        for (key, value) in &parts.headers {
+            if !HOP_BY_HOP.contains(&key.as_str()) {
+                headers.append(key.clone(), value.clone());
+            }
+        }
+
+        let send = |base: &Url| {
+            let request = self
+                .inner
+                .client
+                .request(parts.method.clone(), join(base, path))
+                .headers(headers.clone())
+                .body(body.clone());
+            async move {
+                let start = Instant::now();
+                let res = request.send().await?;
+                let ttfb = start.elapsed();
+                let (version, status, headers) =
+                    (res.version(), res.status(), res.headers().clone());
+                let bytes = res.bytes().await?;
+                let text = String::from_utf8_lossy(&bytes).to_string();
+
+                let res = ResponseExt::new(version, status, headers, text, start.elapsed());
+                Ok::<_, anyhow::Error>((bytes, res.with_ttfb(ttfb)))
+            }
+        };
+
+        // the candidate is sent at the same time, but the client never waits for it
+        let candidate = tokio::spawn(send(&self.inner.candidate));
+        let (bytes, primary) = send(&self.inner.primary).await?;
+
+        let mut response = Response::builder()
+            .status(primary.status())
+            .body(Body::from(bytes))?;
+        for (key, value) in primary.headers() {
+            if !HOP_BY_HOP.contains(&key.as_str()) && key != "content-length" {
+                response.headers_mut().append(key.clone(), value.clone());
+            }
+        }
+
+        let mirror = self.clone();
+        tokio::spawn(async move {
+            let result = match candidate.await {
+                Ok(candidate) => candidate.and_then(|(_, c)| mirror.compare(&primary, &c)),
+                Err(e) => Err(anyhow!(e)),
+            };
+            mirror.log(&label, result);
+        });
+
+        Ok(response)
+    }
+
+    /// The diff of the two responses, empty when they match.
+    fn compare(&self, primary: &ResponseExt, candidate: &ResponseExt) -> Result<String> {
+        let diff = ResponseDiff::new(primary, candidate, &self.inner.response)?;
+
+        response_diff_report(&diff)
+    }
+
+    fn log(&self, label: &str, result: Result<String>) {
+        let line = match result {
+            Ok(diff) if diff.is_empty() => {
+                format!("{}: {}", label, Style::new().green().apply_to("identical"))
+            }
+            Ok(diff) => {
+                self.inner.differences.fetch_add(1, Ordering::SeqCst);
+                format!(
+                    "{}: {}\n{}",
+                    label,
+                    Style::new().red().apply_to("differs"),
+                    diff.trim_end()
+                )
+            }
+            Err(e) => format!(
+                "{}: {}",
+                label,
+                Style::new()
+                    .red()
+                    .apply_to(format!("candidate failed: {}", e))
+            ),
+        };
+        let count = self.inner.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        let differences = self.differences();
+
+        // a closed stdout must not stop the proxy
+        let _ = writeln!(
+            stdout().lock(),
+            "[{} requests, {} differ] {}",
+            count,
+            differences,
+            line
+        );
+    }
+}
+
+fn join(base: &Url, path_and_query: &str) -> String {
+    format!("{}{}", base.as_str().trim_end_matches('/'), path_and_query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use hyper::body::Bytes;
+    use std::{sync::Mutex, time::Duration};
+
+    type Seen = Arc<Mutex<Vec<(String, HeaderMap, Bytes)>>>;
+
+    /// An upstream answering with `body` that records every request it gets.
+    fn upstream(body: &'static str) -> (Url, Seen) {
+        let seen = Seen::default();
+        let log = seen.clone();
+        let addr = serve(move |parts, bytes| {
+            let path = parts.uri.path_and_query().unwrap().to_string();
+            log.lock()
+                .unwrap()
+                .push((path.clone(), parts.headers, bytes));
+            let builder = Response::builder()
+                .header("x-upstream", body)
+                .header("proxy-authenticate", "Basic");
+            match path.as_str() {
+                "/api/redirect" => builder.status(302).header("location", "/elsewhere"),
+                _ => builder.status(201),
+            }
+            .body(Body::from(body))
+            .unwrap()
+        });
+        (format!("http://{}/api", addr).parse().unwrap(), seen)
+    }
+
+    async fn compared(mirror: &Mirror, count: usize) {
+        for _ in 0..100 {
+            if mirror.requests() == count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("the candidate was not compared");
+    }
+
+    #[tokio::test]
+    async fn clients_get_the_primary_response_without_hop_by_hop_headers() {
+        let ((primary, primary_seen), (candidate, candidate_seen)) = (upstream("a"), upstream("a"));
+        let mirror = Mirror::new(primary, candidate, ResponseProfile::default()).unwrap();
+
+        let req = Request::post("/todos?id=1")
+            .header("x-token", "t")
+            .header("te", "trailers")
+            .header("proxy-authorization", "Basic x")
+            .header("host", "proxy.local")
+            .body(Body::from("{\"a\":1}"))
+            .unwrap();
+        let res = mirror.handle(req).await;
+
+        assert_eq!(res.status(), 201);
+        assert_eq!(res.headers()["x-upstream"], "a");
+        assert!(res.headers().get("proxy-authenticate").is_none());
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), "a");
+
+        compared(&mirror, 1).await;
+        assert_eq!(mirror.differences(), 0);
+        for seen in [primary_seen, candidate_seen] {
+            let (path, headers, body) = seen.lock().unwrap().pop().unwrap();
+            assert_eq!(path, "/api/todos?id=1");
+            assert_eq!(headers["x-token"], "t");
+            assert!(headers.get("te").is_none());
+            assert!(headers.get("proxy-authorization").is_none());
+            assert_ne!(headers["host"], "proxy.local");
+            assert_eq!(body, "{\"a\":1}");
+        }
+    }
+
+    #[tokio::test]
+    async fn differing_candidates_are_counted_and_redirects_passed_on() {
+        let ((primary, _), (candidate, _)) = (upstream("a"), upstream("b"));
+        let mirror = Mirror::new(primary, candidate, ResponseProfile::default()).unwrap();
+
+        let res = mirror
+            .handle(Request::get("/redirect").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(res.status(), 302);
+        assert_eq!(res.headers()["location"], "/elsewhere");
+
+        compared(&mirror, 1).await;
+        assert_eq!(mirror.differences(), 1);
+
+        let response = ResponseProfile::new(vec!["x-upstream".to_string()], vec![]);
+        let (primary, candidate) = (upstream("a").0, upstream("a").0);
+        let mirror = Mirror::new(primary, candidate, response).unwrap();
+        mirror
+            .handle(Request::get("/").body(Body::empty()).unwrap())
+            .await;
+        compared(&mirror, 1).await;
+        assert_eq!(mirror.differences(), 0);
+    }
+}
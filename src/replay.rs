@@ -0,0 +1,131 @@
+use futures::{stream, StreamExt};
+use url::Url;
+
+use crate::{
+    diff::{Difference, ResponseDiff},
+    import::LogEntry,
+    ExtraArgs, ResponseProfile,
+};
+
+/// Outcome of replaying one log entry.
+#[derive(Debug, Clone)]
+pub struct ReplayResult {
+    pub entry: LogEntry,
+    /// empty when the request failed
+    pub diff: ResponseDiff,
+    /// set when a request could not be built or sent
+    pub error: Option<String>,
+}
+
+impl ReplayResult {
+    /// The sections of the responses that differ.
+    pub fn differences(&self) -> Vec<Difference> {
+        self.diff.differences()
+    }
+}
+
+/// Send every entry to both environments, `concurrency` entries at a time, and compare the
+/// responses with the settings of `response`. Results are in the order of `entries`.
+pub async fn replay(
+    entries: Vec<LogEntry>,
+    base1: &Url,
+    base2: &Url,
+    response: &ResponseProfile,
+    concurrency: usize,
+) -> Vec<ReplayResult> {
+    let extra = ExtraArgs::default();
+    let results = stream::iter(entries.into_iter().enumerate())
+        .map(|(i, entry)| {
+            let extra = &extra;
+            async move {
+                let result = async {
+                    let request1 = entry.to_request(base1)?;
+                    let request2 = entry.to_request(base2)?;
+                    let (res1, res2) =
+                        tokio::try_join!(request1.send(extra), request2.send(extra))?;
+                    ResponseDiff::new(&res1, &res2, response)
+                }
+                .await;
+
+                let (diff, error) = match result {
+                    Ok(diff) => (diff, None),
+                    Err(e) => (ResponseDiff::default(), Some(e.to_string())),
+                };
+                (i, ReplayResult { entry, diff, error })
+            }
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut results = results;
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{import::load_log, test_server::serve};
+    use hyper::{Body, Response};
+
+    fn base(version: &'static str) -> Url {
+        let addr = serve(move |parts, _| {
+            let status = match parts.uri.path() {
+                "/missing" => 404,
+                _ => 200,
+            };
+            let body = match parts.uri.path() {
+                "/version" => version,
+                _ => "same",
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from(body))
+                .unwrap()
+        });
+        format!("http://{}", addr).parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn replay_keeps_the_log_order_and_reports_each_entry() {
+        let log = [
+            r#"{"path": "/version"}"#,
+            r#"{"path": "/same"}"#,
+            r#"{"path": "/missing"}"#,
+            r#"{"method": "not a method", "path": "/same"}"#,
+            r#"{"path": "/same", "headers": {"content-type": "application/json; charset=utf-8"}}"#,
+            r#"{"method": "post", "path": "/same", "body": "x", "headers": {"content-type": "text/plain"}}"#,
+        ]
+        .join("\n");
+        let entries = load_log(&log).unwrap();
+
+        let results = replay(
+            entries,
+            &base("1"),
+            &base("2"),
+            &ResponseProfile::default(),
+            3,
+        )
+        .await;
+
+        let paths = results
+            .iter()
+            .map(|r| r.entry.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            ["/version", "/same", "/missing", "/same", "/same", "/same"]
+        );
+        assert_eq!(results[0].differences(), [Difference::Body]);
+        assert_eq!(
+            results[0].diff.body_text,
+            ("1\n".to_string(), "2\n".to_string())
+        );
+        assert!(results[1].diff.is_empty() && results[1].error.is_none());
+        assert!(results[2].diff.is_empty());
+        assert!(results[3].error.is_some());
+        assert!(results[4].error.is_none());
+        assert!(results[5].error.is_some());
+    }
+}
@@ -0,0 +1,413 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+
+/// How many references are followed before a schema is considered recursive without end.
+const MAX_DEPTH: usize = 32;
+
+/// A place where a value does not match its schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SchemaViolation {
+    /// JSON path of the offending value, e.g. `$.items[0].id`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Validates values against a JSON Schema, or an OpenAPI schema object. Local `$ref`s are
+/// resolved against `root`, the document the schema was taken from.
+pub struct Validator<'a> {
+    root: &'a Value,
+}
+
+impl<'a> Validator<'a> {
+    pub fn new(root: &'a Value) -> Self {
+        Self { root }
+    }
+
+    pub fn validate(&self, schema: &Value, instance: &Value) -> Result<Vec<SchemaViolation>> {
+        let mut violations = vec![];
+        self.check(schema, instance, "$", 0, &mut violations)?;
+        Ok(violations)
+    }
+
+    /// Follow a local `$ref` such as `#/components/schemas/Todo`.
+    pub fn resolve(&self, reference: &str) -> Result<&'a Value> {
+        let pointer = reference
+            .strip_prefix('#')
+            .ok_or_else(|| anyhow!("Only local references are supported: {}", reference))?;
+        self.root
+            .pointer(pointer)
+            .ok_or_else(|| anyhow!("Unresolved reference: {}", reference))
+    }
+
+    fn check(
+        &self,
+        schema: &Value,
+        instance: &Value,
+        path: &str,
+        depth: usize,
+        violations: &mut Vec<SchemaViolation>,
+    ) -> Result<()> {
+        if depth > MAX_DEPTH {
+            bail!("Schema nested too deep at {}", path);
+        }
+
+        let schema = match schema {
+            Value::Bool(true) => return Ok(()),
+            Value::Bool(false) => {
+                violations.push(violation(path, "no value is allowed here"));
+                return Ok(());
+            }
+            Value::Object(schema) => schema,
+            _ => bail!("Invalid schema at {}", path),
+        };
+
+        if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+            return self.check(
+                self.resolve(reference)?,
+                instance,
+                path,
+                depth + 1,
+                violations,
+            );
+        }
+
+        // openapi 3.0
+        if instance.is_null() && schema.get("nullable") == Some(&Value::Bool(true)) {
+            return Ok(());
+        }
+
+        if let Some(types) = schema.get("type") {
+            let types = match types {
+                Value::Array(types) => types.iter().filter_map(Value::as_str).collect(),
+                v => v.as_str().into_iter().collect::<Vec<_>>(),
+            };
+            if !types.iter().any(|t| type_matches(t, instance)) {
+                violations.push(violation(
+                    path,
+                    format!(
+                        "expected {}, got {}",
+                        types.join(" or "),
+                        type_name(instance)
+                    ),
+                ));
+                return Ok(());
+            }
+        }
+
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            if !values.contains(instance) {
+                let values = values.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                violations.push(violation(
+                    path,
+                    format!("{} is not one of {}", instance, values.join(", ")),
+                ));
+            }
+        }
+        if let Some(value) = schema.get("const") {
+            if value != instance {
+                violations.push(violation(
+                    path,
+                    format!("expected {}, got {}", value, instance),
+                ));
+            }
+        }
+
+        match instance {
+            Value::Object(map) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for name in schema
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                {
+                    if !map.contains_key(name) {
+                        violations.push(violation(path, format!("missing property {}", name)));
+                    }
+                }
+
+                for (key, value) in map {
+                    let child = format!("{}.{}", path, key);
+                    match (
+                        properties.and_then(|p| p.get(key)),
+                        schema.get("additionalProperties"),
+                    ) {
+                        (Some(s), _) => self.check(s, value, &child, depth + 1, violations)?,
+                        (None, Some(Value::Bool(false))) => {
+                            violations.push(violation(&child, "property is not allowed"))
+                        }
+                        (None, Some(s)) => self.check(s, value, &child, depth + 1, violations)?,
+                        (None, None) => {}
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(s) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        let child = format!("{}[{}]", path, i);
+                        self.check(s, item, &child, depth + 1, violations)?;
+                    }
+                }
+                check_bound(
+                    schema,
+                    "minItems",
+                    "maxItems",
+                    items.len(),
+                    "items",
+                    path,
+                    violations,
+                );
+            }
+            Value::String(s) => {
+                let len = s.chars().count();
+                check_bound(
+                    schema,
+                    "minLength",
+                    "maxLength",
+                    len,
+                    "characters",
+                    path,
+                    violations,
+                );
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                    if !Regex::new(pattern)?.is_match(s) {
+                        violations.push(violation(path, format!("does not match /{}/", pattern)));
+                    }
+                }
+            }
+            Value::Number(n) => {
+                let n = n.as_f64().unwrap_or_default();
+                check_range(schema, n, path, violations);
+            }
+            _ => {}
+        }
+
+        if let Some(all) = schema.get("allOf").and_then(Value::as_array) {
+            for s in all {
+                self.check(s, instance, path, depth + 1, violations)?;
+            }
+        }
+
+        for key in ["anyOf", "oneOf"] {
+            if let Some(options) = schema.get(key).and_then(Value::as_array) {
+                let mut matched = 0;
+                for s in options {
+                    let mut v = vec![];
+                    self.check(s, instance, path, depth + 1, &mut v)?;
+                    matched += v.is_empty() as usize;
+                }
+                if matched == 0 || (key == "oneOf" && matched > 1) {
+                    violations.push(violation(
+                        path,
+                        format!(
+                            "matches {} of the {} schemas in {}",
+                            matched,
+                            options.len(),
+                            key
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(s) = schema.get("not") {
+            let mut v = vec![];
+            self.check(s, instance, path, depth + 1, &mut v)?;
+            if v.is_empty() {
+                violations.push(violation(path, "matches a schema it must not match"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn violation(path: &str, message: impl Into<String>) -> SchemaViolation {
+    SchemaViolation {
+        path: path.to_string(),
+        message: message.into(),
+    }
+}
+
+fn type_matches(t: &str, value: &Value) -> bool {
+    match t {
+        "integer" => {
+            value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|v| v.fract() == 0.0)
+        }
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        "null" => value.is_null(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn check_bound(
+    schema: &serde_json::Map<String, Value>,
+    min: &str,
+    max: &str,
+    len: usize,
+    unit: &str,
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+) {
+    if let Some(min) = schema.get(min).and_then(Value::as_u64) {
+        if (len as u64) < min {
+            violations.push(violation(
+                path,
+                format!("expected at least {} {}, got {}", min, unit, len),
+            ));
+        }
+    }
+    if let Some(max) = schema.get(max).and_then(Value::as_u64) {
+        if len as u64 > max {
+            violations.push(violation(
+                path,
+                format!("expected at most {} {}, got {}", max, unit, len),
+            ));
+        }
+    }
+}
+
+fn check_range(
+    schema: &serde_json::Map<String, Value>,
+    n: f64,
+    path: &str,
+    violations: &mut Vec<SchemaViolation>,
+) {
+    // openapi 3.0 uses booleans for the exclusive flags, json schema uses numbers
+    let exclusive = |key: &str, bound: &str| match schema.get(key) {
+        Some(Value::Bool(true)) => schema.get(bound).and_then(Value::as_f64),
+        Some(v) => v.as_f64(),
+        None => None,
+    };
+
+    if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+        if n < min {
+            violations.push(violation(path, format!("{} is less than {}", n, min)));
+        }
+    }
+    if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+        if n > max {
+            violations.push(violation(path, format!("{} is greater than {}", n, max)));
+        }
+    }
+    if let Some(min) = exclusive("exclusiveMinimum", "minimum") {
+        if n <= min {
+            violations.push(violation(
+                path,
+                format!("{} is not greater than {}", n, min),
+            ));
+        }
+    }
+    if let Some(max) = exclusive("exclusiveMaximum", "maximum") {
+        if n >= max {
+            violations.push(violation(path, format!("{} is not less than {}", n, max)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn doc() -> Value {
+        json!({
+            "components": {"schemas": {
+                "Todo": {
+                    "type": "object",
+                    "required": ["id", "title"],
+                    "additionalProperties": false,
+                    "properties": {
+                        "id": {"type": "integer", "minimum": 1},
+                        "title": {"type": "string", "minLength": 1, "pattern": "^[a-z]"},
+                        "tags": {"type": "array", "items": {"type": "string"}, "maxItems": 2},
+                        "owner": {"type": "string", "nullable": true},
+                        "state": {"enum": ["open", "done"]}
+                    }
+                }
+            }}
+        })
+    }
+
+    fn violations(instance: Value) -> Vec<String> {
+        let doc = doc();
+        let validator = Validator::new(&doc);
+        let schema = validator.resolve("#/components/schemas/Todo").unwrap();
+        validator
+            .validate(schema, &instance)
+            .unwrap()
+            .into_iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_instance_has_no_violations() {
+        let todo = json!({"id": 1, "title": "a", "tags": ["x"], "owner": null, "state": "open"});
+        assert_eq!(violations(todo), Vec::<String>::new());
+    }
+
+    #[test]
+    fn violations_point_at_the_value() {
+        let todo = json!({"id": 0, "title": "A", "tags": ["x", 1, "z"], "state": "new", "x": 1});
+        assert_eq!(
+            violations(todo),
+            [
+                "$.id: 0 is less than 1",
+                "$.state: \"new\" is not one of \"open\", \"done\"",
+                "$.tags[1]: expected string, got number",
+                "$.tags: expected at most 2 items, got 3",
+                "$.title: does not match /^[a-z]/",
+                "$.x: property is not allowed",
+            ]
+        );
+        assert_eq!(violations(json!({})).len(), 2);
+        assert_eq!(violations(json!([])), ["$: expected object, got array"]);
+    }
+
+    #[test]
+    fn combinators() {
+        let doc = json!({});
+        let validator = Validator::new(&doc);
+        let one_of = json!({"oneOf": [{"type": "integer"}, {"type": "number"}]});
+        assert_eq!(validator.validate(&one_of, &json!(1.5)).unwrap(), []);
+        assert_eq!(validator.validate(&one_of, &json!(1)).unwrap().len(), 1);
+
+        let not = json!({"not": {"type": "null"}});
+        assert_eq!(validator.validate(&not, &json!(null)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unresolved_and_recursive_refs_fail() {
+        let doc = json!({"a": {"$ref": "#/a"}});
+        let validator = Validator::new(&doc);
+        assert!(validator.resolve("#/missing").is_err());
+        assert!(validator.validate(&doc["a"], &json!(1)).is_err());
+    }
+}
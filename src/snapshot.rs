@@ -0,0 +1,122 @@
+use std::path::Path;
+
+use anyhow::Result;
+use tokio::fs;
+
+use crate::{
+    diff::{ResponseDiff, ResponseSections},
+    utils::{response_diff_report, text_diff},
+};
+
+/// The result of comparing a live response against its recorded snapshot.
+#[derive(Debug)]
+pub enum SnapshotStatus {
+    /// No snapshot existed, so the response was recorded.
+    Created,
+    Unchanged,
+    /// The snapshot differed and was overwritten with the live response.
+    Updated(String),
+    /// The snapshot differs from the live response. Holds the diff.
+    Changed(String),
+}
+
+impl SnapshotStatus {
+    pub fn is_changed(&self) -> bool {
+        matches!(self, SnapshotStatus::Changed(_))
+    }
+}
+
+/// Compare `text` with the snapshot at `path`, recording it if the snapshot is missing or
+/// `update` is set.
+pub async fn check_snapshot(path: &Path, text: &str, update: bool) -> Result<SnapshotStatus> {
+    let recorded = match fs::read_to_string(path).await {
+        Ok(recorded) => recorded,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            write_snapshot(path, text).await?;
+            return Ok(SnapshotStatus::Created);
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    if recorded == text {
+        return Ok(SnapshotStatus::Unchanged);
+    }
+
+    let diff = snapshot_diff(&recorded, text)?;
+    if update {
+        write_snapshot(path, text).await?;
+        Ok(SnapshotStatus::Updated(diff))
+    } else {
+        Ok(SnapshotStatus::Changed(diff))
+    }
+}
+
+/// The status, headers and body sections that differ when both texts are responses, e.g. from
+/// [`crate::ResponseExt::get_text`], otherwise a plain text diff.
+fn snapshot_diff(recorded: &str, text: &str) -> Result<String> {
+    if let (Some(old), Some(new)) = (
+        ResponseSections::parse(recorded),
+        ResponseSections::parse(text),
+    ) {
+        let diff = ResponseDiff::between(&old, &new);
+        // e.g. only the http version or reason phrase changed
+        if !diff.is_empty() {
+            return response_diff_report(&diff);
+        }
+    }
+
+    text_diff(recorded.to_string(), text.to_string())
+}
+
+async fn write_snapshot(path: &Path, text: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(path, text).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn snapshot_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/todo.snap");
+
+        let status = check_snapshot(&path, "a\n", false).await.unwrap();
+        assert!(matches!(status, SnapshotStatus::Created));
+        let status = check_snapshot(&path, "a\n", false).await.unwrap();
+        assert!(matches!(status, SnapshotStatus::Unchanged));
+
+        let status = check_snapshot(&path, "b\n", false).await.unwrap();
+        assert!(status.is_changed());
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), "a\n");
+
+        let status = check_snapshot(&path, "b\n", true).await.unwrap();
+        assert!(matches!(status, SnapshotStatus::Updated(_)));
+        assert_eq!(fs::read_to_string(&path).await.unwrap(), "b\n");
+    }
+
+    #[tokio::test]
+    async fn changed_responses_are_reported_by_section() {
+        console::set_colors_enabled(false);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("todo.snap");
+        let old = "HTTP/1.1 200 OK\netag: 1\nvary: a\n\n{}\n";
+        check_snapshot(&path, old, false).await.unwrap();
+
+        let new = "HTTP/1.1 200 OK\netag: 2\nvary: a\nvary: b\n\n{}\n";
+        let SnapshotStatus::Changed(diff) = check_snapshot(&path, new, false).await.unwrap() else {
+            panic!("the snapshot should have changed");
+        };
+        assert_eq!(diff, "headers:\n  ~ etag: 1 -> 2\n  + vary: b\n");
+
+        // the sections match, so the plain text diff shows what changed
+        let new = "HTTP/2.0 200 OK\netag: 1\nvary: a\n\n{}\n";
+        let status = check_snapshot(&path, new, false).await.unwrap();
+        assert!(matches!(status, SnapshotStatus::Changed(diff) if diff.contains("HTTP/2.0")));
+    }
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use serde::{Serialize, Serializer};
+
+use crate::ResponseExt;
+
+/// Timing and size of a single response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub ttfb: Duration,
+    pub total: Duration,
+    pub body_size: usize,
+    pub header_count: usize,
+}
+
+impl Sample {
+    pub fn from_response(res: &ResponseExt) -> Self {
+        Self {
+            ttfb: res.ttfb(),
+            total: res.elapsed(),
+            body_size: res.body().len(),
+            header_count: res.headers().len(),
+        }
+    }
+}
+
+/// Minimum, median and 95th percentile of a series of values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Summary<T> {
+    pub min: T,
+    pub median: T,
+    pub p95: T,
+}
+
+impl<T: Ord + Copy> Summary<T> {
+    /// `values` must not be empty. Percentiles use the nearest rank.
+    pub fn new(mut values: Vec<T>) -> Self {
+        values.sort();
+        let rank = |p: usize| values[((values.len() * p).div_ceil(100)).max(1) - 1];
+
+        Self {
+            min: values[0],
+            median: rank(50),
+            p95: rank(95),
+        }
+    }
+}
+
+/// The samples of one side of a diff. Durations are serialized in milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub count: usize,
+    #[serde(rename = "ttfb_ms", serialize_with = "summary_ms")]
+    pub ttfb: Summary<Duration>,
+    #[serde(rename = "total_ms", serialize_with = "summary_ms")]
+    pub total: Summary<Duration>,
+    pub body_size: Summary<usize>,
+    pub header_count: Summary<usize>,
+}
+
+impl Stats {
+    /// `samples` must not be empty.
+    pub fn new(samples: &[Sample]) -> Self {
+        let summary = |f: fn(&Sample) -> usize| Summary::new(samples.iter().map(f).collect());
+        let durations = |f: fn(&Sample) -> Duration| Summary::new(samples.iter().map(f).collect());
+
+        Self {
+            count: samples.len(),
+            ttfb: durations(|s| s.ttfb),
+            total: durations(|s| s.total),
+            body_size: summary(|s| s.body_size),
+            header_count: summary(|s| s.header_count),
+        }
+    }
+}
+
+fn summary_ms<S: Serializer>(
+    summary: &Summary<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let ms = |d: Duration| d.as_millis() as u64;
+    Summary {
+        min: ms(summary.min),
+        median: ms(summary.median),
+        p95: ms(summary.p95),
+    }
+    .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(total_ms: u64, body_size: usize) -> Sample {
+        Sample {
+            ttfb: Duration::from_millis(total_ms / 2),
+            total: Duration::from_millis(total_ms),
+            body_size,
+            header_count: 3,
+        }
+    }
+
+    #[test]
+    fn summary_uses_nearest_rank_percentiles() {
+        assert_eq!(
+            Summary::new((1..=20).rev().collect()),
+            Summary {
+                min: 1,
+                median: 10,
+                p95: 19
+            }
+        );
+        assert_eq!(
+            Summary::new(vec![7]),
+            Summary {
+                min: 7,
+                median: 7,
+                p95: 7
+            }
+        );
+    }
+
+    #[test]
+    fn stats_summarize_each_measure() {
+        let stats = Stats::new(&[sample(30, 10), sample(10, 30), sample(20, 20)]);
+
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.total.min, Duration::from_millis(10));
+        assert_eq!(stats.total.median, Duration::from_millis(20));
+        assert_eq!(stats.ttfb.p95, Duration::from_millis(15));
+        assert_eq!(stats.body_size.median, 20);
+        assert_eq!(stats.header_count.min, 3);
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(
+            json["total_ms"],
+            serde_json::json!({"min": 10, "median": 20, "p95": 30})
+        );
+        assert_eq!(json["ttfb_ms"]["min"], 5);
+        assert_eq!(json["body_size"]["median"], 20);
+    }
+}
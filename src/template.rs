@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+/// Variables available to `{{ name }}` placeholders.
+pub type Vars = HashMap<String, Value>;
+
+/// Replace every `{{ name }}` placeholder in `s`. String values are inserted without quotes.
+pub fn render_str(s: &str, vars: &Vars) -> Result<String> {
+    let mut output = String::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..]
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unclosed placeholder in: {}", s))?;
+        let name = rest[start + 2..start + end].trim();
+        let value = vars
+            .get(name)
+            .ok_or_else(|| anyhow!("Undefined variable: {}", name))?;
+
+        output.push_str(&rest[..start]);
+        match value {
+            Value::String(v) => output.push_str(v),
+            v => output.push_str(&v.to_string()),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+/// Render every string in `value`. A string that is exactly one placeholder is replaced by the
+/// variable itself, so numbers and objects keep their type.
+pub fn render_value(value: &Value, vars: &Vars) -> Result<Value> {
+    Ok(match value {
+        Value::String(s) => match whole_placeholder(s) {
+            Some(name) => vars
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Undefined variable: {}", name))?,
+            None => Value::String(render_str(s, vars)?),
+        },
+        Value::Array(arr) => Value::Array(
+            arr.iter()
+                .map(|v| render_value(v, vars))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| Ok((k.clone(), render_value(v, vars)?)))
+                .collect::<Result<_>>()?,
+        ),
+        v => v.clone(),
+    })
+}
+
+fn whole_placeholder(s: &str) -> Option<&str> {
+    let name = s.trim().strip_prefix("{{")?.strip_suffix("}}")?;
+    (!name.contains("{{") && !name.contains("}}")).then(|| name.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn vars() -> Vars {
+        Vars::from([
+            ("token".to_string(), json!("abc")),
+            ("id".to_string(), json!(42)),
+        ])
+    }
+
+    #[test]
+    fn render_str_inserts_strings_without_quotes() {
+        assert_eq!(
+            render_str("Bearer {{ token }}/{{id}}", &vars()).unwrap(),
+            "Bearer abc/42"
+        );
+        assert!(render_str("{{ missing }}", &vars()).is_err());
+        assert!(render_str("{{ token", &vars()).is_err());
+    }
+
+    #[test]
+    fn render_value_keeps_the_type_of_whole_placeholders() {
+        let value = json!({"id": "{{ id }}", "path": "/items/{{ id }}", "list": ["{{token}}"]});
+        assert_eq!(
+            render_value(&value, &vars()).unwrap(),
+            json!({"id": 42, "path": "/items/42", "list": ["abc"]})
+        );
+    }
+}
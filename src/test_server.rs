@@ -0,0 +1,49 @@
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    body::{to_bytes, Bytes},
+    http::request::Parts,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+
+/// Serve `handler` on a free local port for the rest of the test. The server has a runtime of
+/// its own, so a handler may block, e.g. to play a slow backend, without stalling the test.
+pub(crate) fn serve<F>(handler: F) -> SocketAddr
+where
+    F: Fn(Parts, Bytes) -> Response<Body> + Send + Sync + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    listener.set_nonblocking(true).unwrap();
+
+    let handler = Arc::new(handler);
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async move {
+            let make_service = make_service_fn(move |_| {
+                let handler = handler.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let handler = handler.clone();
+                        async move {
+                            let (parts, body) = req.into_parts();
+                            let body = to_bytes(body).await.unwrap_or_default();
+                            let res = tokio::task::block_in_place(|| handler(parts, body));
+                            Ok::<_, Infallible>(res)
+                        }
+                    }))
+                }
+            });
+            Server::from_tcp(listener)
+                .unwrap()
+                .serve(make_service)
+                .await
+        })
+    });
+
+    addr
+}
@@ -1,12 +1,24 @@
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::Write as _;
 use std::io::Write as _;
+use std::time::Duration;
 
 use anyhow::{Error, Result};
 use atty::Stream;
 use console::{style, Style};
 use similar::{ChangeTag, TextDiff};
 
+use crate::{
+    config::AssertionResult,
+    diff::ResponseDiff,
+    load::LoadReport,
+    replay::ReplayResult,
+    schema::SchemaViolation,
+    snapshot::SnapshotStatus,
+    stats::{Stats, Summary},
+};
+
 use syntect::easy::HighlightLines;
 use syntect::highlighting::ThemeSet;
 use syntect::parsing::SyntaxSet;
@@ -84,6 +96,332 @@ pub fn highlight(text: &str, language: &str) -> Result<String> {
     Ok(output)
 }
 
+pub fn assertion_report(results: &[AssertionResult]) -> Result<String> {
+    let mut output = String::new();
+
+    for result in results {
+        let s = if result.passed {
+            Style::new().green()
+        } else {
+            Style::new().red()
+        };
+        writeln!(&mut output, "{}", s.apply_to(result))?;
+    }
+
+    let passed = results.iter().filter(|r| r.passed).count();
+    write!(
+        &mut output,
+        "{} passed, {} failed",
+        passed,
+        results.len() - passed
+    )?;
+
+    Ok(output)
+}
+
+pub fn snapshot_report(name: &str, status: &SnapshotStatus) -> String {
+    match status {
+        SnapshotStatus::Created => {
+            format!("{}: {}", name, Style::new().green().apply_to("created"))
+        }
+        SnapshotStatus::Unchanged => {
+            format!("{}: {}", name, Style::new().dim().apply_to("unchanged"))
+        }
+        SnapshotStatus::Updated(diff) => {
+            format!(
+                "{}: {}\n{}",
+                name,
+                Style::new().yellow().apply_to("updated"),
+                diff
+            )
+        }
+        SnapshotStatus::Changed(diff) => {
+            format!(
+                "{}: {}\n{}",
+                name,
+                Style::new().red().apply_to("changed"),
+                diff
+            )
+        }
+    }
+}
+
+pub fn schema_report(name: &str, violations: &[SchemaViolation]) -> String {
+    if violations.is_empty() {
+        return format!(
+            "{}: {}",
+            name,
+            Style::new().green().apply_to("matches schema")
+        );
+    }
+
+    let mut output = format!(
+        "{}: {}",
+        name,
+        Style::new()
+            .red()
+            .apply_to(format!("{} schema violations", violations.len()))
+    );
+    for violation in violations {
+        output.push_str(&format!("\n  {}", Style::new().red().apply_to(violation)));
+    }
+
+    output
+}
+
+pub fn compare_report(name: &str, baseline: &str, matches: bool) -> String {
+    if matches {
+        format!(
+            "{}: {}",
+            name,
+            Style::new()
+                .green()
+                .apply_to(format!("matches {}", baseline))
+        )
+    } else {
+        format!(
+            "{}: {}",
+            name,
+            Style::new()
+                .red()
+                .apply_to(format!("differs from {}", baseline))
+        )
+    }
+}
+
+/// The differing sections of `diff`, each under its own heading. Empty when the responses
+/// match.
+pub fn response_diff_report(diff: &ResponseDiff) -> Result<String> {
+    let mut output = String::new();
+
+    if let Some(status) = &diff.status {
+        writeln!(
+            &mut output,
+            "status: {} -> {}",
+            Style::new().red().apply_to(status.old),
+            Style::new().green().apply_to(status.new)
+        )?;
+    }
+
+    if !diff.headers.is_empty() {
+        writeln!(&mut output, "headers:")?;
+        for change in &diff.headers {
+            if let ([old], [new]) = (&change.old[..], &change.new[..]) {
+                let line = format!("~ {}: {} -> {}", change.name, old, new);
+                writeln!(&mut output, "  {}", Style::new().yellow().apply_to(line))?;
+                continue;
+            }
+            // one line per value, leaving out the values both sides have
+            for old in values_without(&change.old, &change.new) {
+                let line = format!("- {}: {}", change.name, old);
+                writeln!(&mut output, "  {}", Style::new().red().apply_to(line))?;
+            }
+            for new in values_without(&change.new, &change.old) {
+                let line = format!("+ {}: {}", change.name, new);
+                writeln!(&mut output, "  {}", Style::new().green().apply_to(line))?;
+            }
+        }
+    }
+
+    if diff.body.is_some() {
+        let (body1, body2) = &diff.body_text;
+        writeln!(&mut output, "body:")?;
+        output.push_str(&text_diff(body1.clone(), body2.clone())?);
+    }
+
+    Ok(output)
+}
+
+/// The `values` left after taking away one of each of `other`.
+fn values_without<'a>(values: &'a [String], other: &[String]) -> Vec<&'a String> {
+    let mut other = other.iter().collect::<Vec<_>>();
+    values
+        .iter()
+        .filter(|v| match other.iter().position(|o| o == v) {
+            Some(i) => {
+                other.swap_remove(i);
+                false
+            }
+            None => true,
+        })
+        .collect()
+}
+
+pub fn stats_report(name: &str, stats: &Stats) -> String {
+    let ms = |d: Duration| d.as_millis();
+    let timing = if stats.count == 1 {
+        format!(
+            "ttfb {}ms, total {}ms",
+            ms(stats.ttfb.min),
+            ms(stats.total.min)
+        )
+    } else {
+        format!(
+            "ttfb {}/{}/{}ms, total {}/{}/{}ms (min/median/p95 of {})",
+            ms(stats.ttfb.min),
+            ms(stats.ttfb.median),
+            ms(stats.ttfb.p95),
+            ms(stats.total.min),
+            ms(stats.total.median),
+            ms(stats.total.p95),
+            stats.count
+        )
+    };
+
+    format!(
+        "{}: {}",
+        name,
+        Style::new().dim().apply_to(format!(
+            "{}, {} bytes, {} headers",
+            timing, stats.body_size.median, stats.header_count.median
+        ))
+    )
+}
+
+pub fn regression_report(regressions: &[String]) -> String {
+    regressions
+        .iter()
+        .map(|r| {
+            format!(
+                "{}",
+                Style::new().red().apply_to(format!("REGRESSION {}", r))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The items an import left out, with the reason for each.
+pub fn skipped_report(skipped: &[(String, Error)]) -> String {
+    skipped
+        .iter()
+        .map(|(name, e)| {
+            format!(
+                "{}",
+                Style::new()
+                    .yellow()
+                    .apply_to(format!("SKIPPED {}: {:#}", name, e))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn load_report(report: &LoadReport) -> Result<String> {
+    let mut output = String::new();
+    let secs = report.elapsed.as_secs_f64();
+    let errors: usize = report.errors.values().sum();
+    writeln!(
+        &mut output,
+        "{} requests in {:.1}s, {:.1}/s, {} errors",
+        report.requests(),
+        secs,
+        report.requests() as f64 / secs.max(f64::EPSILON),
+        errors
+    )?;
+
+    writeln!(&mut output, "status codes:")?;
+    for (status, count) in &report.statuses {
+        let s = if *status < 400 {
+            Style::new().green()
+        } else {
+            Style::new().red()
+        };
+        writeln!(&mut output, "  {}: {}", s.apply_to(status), count)?;
+    }
+    for (error, count) in &report.errors {
+        writeln!(
+            &mut output,
+            "  {}: {}",
+            Style::new().red().apply_to(error),
+            count
+        )?;
+    }
+
+    if report.latencies.is_empty() {
+        return Ok(output);
+    }
+
+    let summary = Summary::new(report.latencies.clone());
+    let max = report.latencies.iter().max().copied().unwrap_or_default();
+    write!(
+        &mut output,
+        "latency: min {}ms, median {}ms, p95 {}ms, max {}ms",
+        summary.min.as_millis(),
+        summary.median.as_millis(),
+        summary.p95.as_millis(),
+        max.as_millis()
+    )?;
+
+    // buckets double in size, starting at 1ms
+    let mut buckets = BTreeMap::new();
+    for latency in &report.latencies {
+        let ms = latency.as_millis().max(1) as u64;
+        *buckets.entry(ms.next_power_of_two()).or_insert(0usize) += 1;
+    }
+    let largest = buckets.values().max().copied().unwrap_or(1);
+    for (bound, count) in buckets {
+        let bar = "#".repeat((count * 40).div_ceil(largest));
+        write!(&mut output, "\n  <= {:>6}ms {} {}", bound, bar, count)?;
+    }
+
+    Ok(output)
+}
+
+pub fn replay_report(results: &[ReplayResult]) -> Result<String> {
+    let mut output = String::new();
+    let counts = |results: &[&ReplayResult]| {
+        (
+            results.iter().filter(|r| !r.diff.is_empty()).count(),
+            results.iter().filter(|r| r.error.is_some()).count(),
+        )
+    };
+
+    let mut endpoints: BTreeMap<String, Vec<&ReplayResult>> = BTreeMap::new();
+    let mut kinds: BTreeMap<String, usize> = BTreeMap::new();
+    for result in results {
+        endpoints
+            .entry(result.entry.endpoint())
+            .or_default()
+            .push(result);
+        for difference in result.differences() {
+            *kinds.entry(difference.to_string()).or_default() += 1;
+        }
+        if result.error.is_some() {
+            *kinds.entry("error".to_string()).or_default() += 1;
+        }
+    }
+
+    let (differ, failed) = counts(&results.iter().collect::<Vec<_>>());
+    writeln!(
+        &mut output,
+        "{} requests, {}, {}",
+        results.len(),
+        Style::new().red().apply_to(format!("{} differ", differ)),
+        Style::new().red().apply_to(format!("{} failed", failed))
+    )?;
+
+    writeln!(&mut output, "by endpoint:")?;
+    for (endpoint, results) in &endpoints {
+        let (differ, failed) = counts(results);
+        writeln!(
+            &mut output,
+            "  {}: {} requests, {} differ, {} failed",
+            endpoint,
+            results.len(),
+            differ,
+            failed
+        )?;
+    }
+
+    write!(&mut output, "by difference:")?;
+    for (kind, count) in &kinds {
+        write!(&mut output, "\n  {}: {}", kind, count)?;
+    }
+
+    Ok(output)
+}
+
 pub fn process_error_output(result: Result<(), Error>) -> Result<()> {
     match result {
         Ok(_) => {}
@@ -103,3 +441,37 @@ pub fn process_error_output(result: Result<(), Error>) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::{HeaderChange, StatusChange};
+
+    fn change(name: &str, old: &[&str], new: &[&str]) -> HeaderChange {
+        let values = |v: &[&str]| v.iter().map(|v| v.to_string()).collect();
+        HeaderChange {
+            name: name.to_string(),
+            old: values(old),
+            new: values(new),
+        }
+    }
+
+    #[test]
+    fn diff_report_has_a_line_per_header_value() {
+        console::set_colors_enabled(false);
+        let diff = ResponseDiff {
+            status: Some(StatusChange { old: 200, new: 500 }),
+            headers: vec![
+                change("etag", &["1"], &["2"]),
+                change("set-cookie", &["a=1", "b=2", "b=2"], &["b=2", "c=3"]),
+                change("x-new", &[], &["1", "2"]),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            response_diff_report(&diff).unwrap(),
+            "status: 200 -> 500\nheaders:\n  ~ etag: 1 -> 2\n  - set-cookie: a=1\n  - set-cookie: b=2\n  + set-cookie: c=3\n  + x-new: 1\n  + x-new: 2\n"
+        );
+    }
+}